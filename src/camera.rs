@@ -0,0 +1,80 @@
+use cgmath::{InnerSpace, Point3, Quaternion, Rad, Rotation, Rotation3, Vector3};
+use super::CamPos;
+
+pub const TURN_RATE: f32 = 45.0;//degrees per second a turn key is held
+pub const SPEED_RATE: f32 = 2.0;//speed is multiplied or divided by this per second faster or slower is held
+
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {//aircraft style camera in render space, x east, y up and z south
+    pub position: CamPos,
+    orientation: Quaternion<f32>,//turns the camera's own axes, forward along -z and up along y, into render space
+    pub speed: f32,//metres per second along the heading
+}
+
+impl Camera {
+    pub fn looking_at(position: CamPos, target: CamPos, speed: f32) -> Self {
+        //wings level and pointing at the target
+        let d = Vector3::new(target.x - position.x, target.y - position.y, target.z - position.z).normalize();
+        let mut camera = Self { position, orientation: Quaternion::new(1.0, 0.0, 0.0, 0.0), speed };
+        camera.set_attitude(d.x.atan2(-d.z).to_degrees(), d.y.clamp(-1.0, 1.0).asin().to_degrees(), 0.0);
+        camera
+    }
+
+    pub fn set_attitude(&mut self, heading: f32, pitch: f32, roll: f32) {
+        //degrees, heading clockwise about the world's up, then pitch about the camera's right, then roll clockwise about its forward
+        self.orientation = Quaternion::from_angle_y(Rad(-heading.to_radians()))
+            * Quaternion::from_angle_x(Rad(pitch.to_radians()))
+            * Quaternion::from_angle_z(Rad(-roll.to_radians()));
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        self.orientation.rotate_vector(-Vector3::unit_z())
+    }
+
+    pub fn up(&self) -> Vector3<f32> {
+        self.orientation.rotate_vector(Vector3::unit_y())
+    }
+
+    pub fn view_points(&self) -> (Point3<f32>, Point3<f32>, Vector3<f32>) {
+        //eye, a point straight ahead and the way up, what the view matrix is built from
+        let eye = Point3::new(self.position.x, self.position.y, self.position.z);
+        (eye, eye + self.forward(), self.up())
+    }
+
+    pub fn heading(&self) -> f32 {
+        //degrees clockwise from north
+        let f = self.forward();
+        f.x.atan2(-f.z).to_degrees().rem_euclid(360.0)
+    }
+
+    pub fn pitch(&self) -> f32 {
+        //degrees above the horizon
+        self.forward().y.clamp(-1.0, 1.0).asin().to_degrees()
+    }
+
+    pub fn roll(&self) -> f32 {
+        //degrees, positive with the right wing down
+        let right = self.orientation.rotate_vector(Vector3::unit_x());
+        (-right.y).atan2(self.up().y).to_degrees()
+    }
+
+    pub fn rotate(&mut self, yaw: f32, pitch: f32, roll: f32) {
+        //degrees about the camera's own axes, the way an aircraft turns
+        self.orientation = (self.orientation
+            * Quaternion::from_angle_y(Rad(-yaw.to_radians()))
+            * Quaternion::from_angle_x(Rad(pitch.to_radians()))
+            * Quaternion::from_angle_z(Rad(-roll.to_radians()))).normalize();
+    }
+
+    pub fn look(&mut self, yaw: f32, pitch: f32) {
+        //mouse look, yaw about the world's up so looking around does not tip the horizon
+        self.orientation = (Quaternion::from_angle_y(Rad(-yaw.to_radians()))
+            * self.orientation
+            * Quaternion::from_angle_x(Rad(pitch.to_radians()))).normalize();
+    }
+
+    pub fn level(&mut self) {
+        //keep the heading and bring the pitch and roll back to 0
+        self.set_attitude(self.heading(), 0.0, 0.0);
+    }
+}
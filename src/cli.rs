@@ -0,0 +1,230 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use super::CamPos;
+use super::settings::Settings;
+use super::color_ramp::{Blend, ColorRamp};
+use super::config::{ConfigFile, DEFAULT_CONFIG};
+use super::flight::Aircraft;
+
+pub const USAGE: &str = "usage: threedcube [options]
+
+  --config FILE.toml        settings file, options given here override it (threedcube.toml if it exists)
+  --start LAT,LONG          where the camera starts, degrees with south and west negative (55.5,-4.5)
+  --view-distance KM        how far out the terrain is drawn, at most one tile's width, about 62 km at 55N (30)
+  --far UNITS               nearest the far clipping plane can be, in render units of one sample (1000)
+  --max-chunks N            most quadtree chunks drawn at once (128)
+  --chunk-size N            vertices along each side of a chunk, every lod step has to divide N - 1 (241)
+  --water-level METRES      anything lower is drawn as water (1)
+  --camera X,Y,Z            camera position in render units (0,100,200)
+  --look X,Y,Z              point the camera looks at in render units (0,100,-30)
+  --speed M/S               how fast the camera flies along its heading, + and - change it (100)
+  --aircraft NAME|FILE.toml aircraft the flight model flies, c172, pa28 or a file of its performance (c172)
+  --free-camera             start with the free camera instead of flying the aircraft, p switches
+  --min-clearance METRES    warn below this height above the ground and hold the camera there (10)
+  --no-floor                let the camera through the ground, the warnings still come
+  --data-dir DIR            directory of hgt or geotiff tiles (src/Scotlandhgt, or SRTM_DATA_DIR)
+  --source hgt|geotiff      elevation file format (hgt, or ELEVATION_SOURCE)
+  --void-fill nearest|idw|laplacian
+                            how holes in the data are filled (idw, or SRTM_VOID_FILL)
+  --interpolation bilinear|bicubic
+                            height sampling between samples (bilinear)
+  --colour-mode elevation|hillshade|slope|aspect|tinted|taws
+                            how the terrain is coloured, c cycles through them (tinted)
+  --ramp NAME               colour ramp, bands, mountain, jet, test or a loaded file's name, g cycles through them (bands)
+  --ramp-file FILE.txt      load a gdal colour relief file and start with it, heights in metres or percent
+  --lod N                   level of detail of every chunk's grid, 0 is full detail (0)
+  --pixel-error PIXELS      screen error allowed before a chunk is split into four smaller ones (2)
+  --size WxH                window or image size in pixels (800x600)
+  --headless FILE.png       render one frame to a png without opening a window
+  --software                use the software adapter for headless rendering
+  --shader FILE.wgsl        read the shader from disk and reload it whenever it changes
+  --help                    show this message";
+
+pub enum Command {
+    Run(Box<Settings>),
+    Help,
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    //args without the program name, options take their value as the next argument or after =
+    let args: Vec<String> = args.into_iter().collect();
+    //the config file goes under every other option so it has to be found first
+    let config = args.iter().enumerate().find_map(|(i, arg)| match arg.strip_prefix("--config") {
+        Some("") => Some(args.get(i + 1).cloned().ok_or_else(|| "--config needs a value".to_string())),
+        Some(value) => value.strip_prefix('=').map(|v| Ok(v.to_string())),
+        None => None,
+    }).transpose()?.map(PathBuf::from);
+    let config = match config {
+        Some(path) => Some(ConfigFile::load(&path)?),
+        None if Path::new(DEFAULT_CONFIG).is_file() => Some(ConfigFile::load(Path::new(DEFAULT_CONFIG))?),
+        None => None,
+    };
+    parse_over(args, config)
+}
+
+fn parse_over(args: Vec<String>, config: Option<ConfigFile>) -> Result<Command, String> {
+    //defaults, then the config file, then the options on the command line
+    let mut settings = Settings::default();
+    if let Some(config) = config {
+        config.apply(&mut settings)?;
+    }
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || inline.clone().or_else(|| args.next()).ok_or_else(|| format!("{} needs a value", name));
+        match name.as_str() {
+            "--help" | "-h" => return Ok(Command::Help),
+            "--config" => {
+                value()?;//already loaded
+            }
+            "--start" => settings.start = parse_value(&name, &value()?)?,
+            "--view-distance" => settings.view_distance = parse_value(&name, &value()?)?,
+            "--far" => settings.far = parse_value(&name, &value()?)?,
+            "--max-chunks" => settings.max_chunks = parse_value(&name, &value()?)?,
+            "--chunk-size" => settings.chunksize = parse_value(&name, &value()?)?,
+            "--water-level" => settings.water_level = parse_value(&name, &value()?)?,
+            "--camera" => settings.camera = parse_position(&name, &value()?)?,
+            "--look" => settings.camlook = parse_position(&name, &value()?)?,
+            "--speed" => settings.speed = parse_value(&name, &value()?)?,
+            "--aircraft" => settings.aircraft = Aircraft::from_name_or_file(&value()?)?,
+            "--free-camera" => settings.fly = false,
+            "--min-clearance" => settings.min_clearance = parse_value(&name, &value()?)?,
+            "--no-floor" => settings.floor = false,
+            "--data-dir" => settings.data_dir = PathBuf::from(value()?),
+            "--source" => settings.source = parse_value(&name, &value()?)?,
+            "--void-fill" => settings.void_fill = parse_value(&name, &value()?)?,
+            "--interpolation" => settings.interpolation = parse_value(&name, &value()?)?,
+            "--colour-mode" => settings.colour_mode = parse_value(&name, &value()?)?,
+            "--ramp" => settings.ramp = value()?,
+            "--ramp-file" => {
+                let ramp = ColorRamp::load_gdal(Path::new(&value()?), Blend::Smooth)?;
+                settings.ramp = ramp.name().to_string();
+                settings.ramp_files.push(ramp);
+            }
+            "--lod" => settings.level_of_detail = parse_value(&name, &value()?)?,
+            "--pixel-error" => settings.pixel_error = parse_value(&name, &value()?)?,
+            "--size" => (settings.width, settings.height) = parse_pair(&name, &value()?)?,
+            "--headless" => settings.headless = Some(PathBuf::from(value()?)),
+            "--software" => settings.force_fallback = true,
+            "--shader" => settings.shader = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+    settings.validate()?;
+    Ok(Command::Run(Box::new(settings)))
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, String>
+where T::Err: ToString {
+    value.trim().parse().map_err(|e: T::Err| format!("{} '{}': {}", name, value, e.to_string()))
+}
+
+fn parse_pair(name: &str, value: &str) -> Result<(u32, u32), String> {
+    //2x2 or 800x600
+    let (a, b) = value.split_once(['x', 'X']).ok_or_else(|| format!("{} '{}': expected two numbers like 2x3", name, value))?;
+    Ok((parse_value(name, a)?, parse_value(name, b)?))
+}
+
+fn parse_position(name: &str, value: &str) -> Result<CamPos, String> {
+    let parts: Vec<&str> = value.split(',').collect();
+    if parts.len() != 3 {
+        return Err(format!("{} '{}': expected x,y,z", name, value));
+    }
+    Ok(CamPos { x: parse_value(name, parts[0])?, y: parse_value(name, parts[1])?, z: parse_value(name, parts[2])? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+[view]
+distance = 20.0
+fov = 60.0
+
+[chunks]
+size = 121
+
+[lod]
+steps = [1, 2, 4]
+"#;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn settings(list: &[&str], file: Option<&str>) -> Result<Settings, String> {
+        //validation wants a data directory that exists, any will do
+        let config = file.map(|text| ConfigFile::from_toml(text, Path::new(""))).transpose()?;
+        match parse_over([args(&["--data-dir", "src"]), args(list)].concat(), config)? {
+            Command::Run(settings) => Ok(*settings),
+            Command::Help => Err("help".to_string()),
+        }
+    }
+
+    #[test]
+    fn defaults_without_a_file_or_options() {
+        let settings = settings(&[], None).unwrap();
+        let defaults = Settings::default();
+        assert_eq!(settings.view_distance, defaults.view_distance);
+        assert_eq!(settings.chunksize, defaults.chunksize);
+        assert_eq!(settings.fov, defaults.fov);
+    }
+
+    #[test]
+    fn file_overrides_the_defaults() {
+        let settings = settings(&[], Some(FILE)).unwrap();
+        assert_eq!(settings.view_distance, 20.0);
+        assert_eq!(settings.fov, 60.0);
+        assert_eq!(settings.chunksize, 121);
+        assert_eq!(settings.lod_steps, vec![1, 2, 4]);
+        assert_eq!(settings.max_chunks, Settings::default().max_chunks);//left out of the file
+    }
+
+    #[test]
+    fn options_override_the_file() {
+        let settings = settings(&["--view-distance", "10", "--chunk-size=61", "--size", "320x200"], Some(FILE)).unwrap();
+        assert_eq!(settings.view_distance, 10.0);
+        assert_eq!(settings.chunksize, 61);
+        assert_eq!((settings.width, settings.height), (320, 200));
+        assert_eq!(settings.fov, 60.0);//only in the file
+    }
+
+    #[test]
+    fn config_option_is_not_taken_for_another_option() {
+        let settings = settings(&["--config", "elsewhere.toml", "--far=2000"], Some(FILE)).unwrap();
+        assert_eq!(settings.far, 2000.0);
+        assert_eq!(settings.fov, 60.0);
+    }
+
+    #[test]
+    fn bad_files_and_options_are_errors() {
+        assert!(ConfigFile::from_toml("[view]\nzoom = 2.0\n", Path::new("")).is_err());//unknown key
+        assert!(ConfigFile::from_toml("[view]\nfov = \"wide\"\n", Path::new("")).is_err());
+        assert!(settings(&["--chunk-size"], None).is_err());//missing value
+        assert!(settings(&["--chunk-size", "many"], None).is_err());
+        assert!(settings(&["--zoom", "2"], None).is_err());
+        //past one tile at the start latitude the terrain is not loaded
+        assert!(settings(&["--start", "55.5,-4.5", "--view-distance", "70"], None).is_err());
+        assert!(settings(&["--start", "0.5,-4.5", "--view-distance", "110"], None).is_ok());
+        //values from the file are validated like any other
+        let err = settings(&[], Some("[chunks]\nsize = 1\n")).unwrap_err();
+        assert!(err.starts_with("chunk size"), "{}", err);
+    }
+
+    #[test]
+    fn help_stops_parsing() {
+        assert!(matches!(parse_over(args(&["--view-distance", "10", "--help"]), None), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn relative_paths_in_the_file_follow_it() {
+        let config = ConfigFile::from_toml("[data]\ndir = \"hgt\"\n", Path::new("/etc/threedcube")).unwrap();
+        let mut settings = Settings::default();
+        config.apply(&mut settings).unwrap();
+        assert_eq!(settings.data_dir, PathBuf::from("/etc/threedcube/hgt"));
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use super::settings::Settings;
+
+pub const BUILTIN_RAMPS: [&str; 3] = ["mountain", "jet", "test"];//11 stop palettes from the old colormap.rs
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopUnit {
+    Metres,
+    Normalised,//0 at the water level to 1 at colour_height_max
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Blend {
+    Smooth,//colours fade between stops
+    Stepped,//each stop's colour holds until the next stop
+}
+
+impl FromStr for Blend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "smooth" => Ok(Blend::Smooth),
+            "stepped" => Ok(Blend::Stepped),
+            _ => Err(format!("unknown blend '{}', expected smooth or stepped", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ColorRamp {//colours for heights
+    name: String,
+    stops: Vec<(f32, [f32; 3])>,//sorted by height, rgb from 0 to 1
+    unit: StopUnit,
+    blend: Blend,
+    water: Option<[f32; 3]>,//drawn below the water level instead of the stops
+}
+
+impl ColorRamp {
+    pub fn new(name: &str, mut stops: Vec<(f32, [f32; 3])>, unit: StopUnit, blend: Blend) -> Result<Self, String> {
+        if stops.is_empty() {
+            return Err(format!("colour ramp {} has no stops", name));
+        }
+        if stops.iter().any(|(h, c)| !h.is_finite() || c.iter().any(|v| !(0.0..=1.0).contains(v))) {
+            return Err(format!("colour ramp {} has a stop that is not a height with rgb from 0 to 1", name));
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Self { name: name.to_string(), stops, unit, blend, water: None })
+    }
+
+    pub fn bands(colours: &[[f32; 3]], thresholds: &[f32]) -> Self {
+        //the original height bands, the first band is water and the rest share out the land above the water level
+        let land = 1.0 - thresholds[1];
+        let stops = thresholds[1..colours.len()].iter().zip(&colours[1..])
+            .map(|(t, c)| ((t - thresholds[1]) / land, *c))
+            .collect();
+        Self { name: "bands".to_string(), stops, unit: StopUnit::Normalised, blend: Blend::Stepped, water: Some(colours[0]) }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        let colors = match name {
+            "mountain" => [[0.0,0.0,1.0],[0.7,0.7,0.5],[0.0,1.0,0.0],[0.0,1.0,0.0],[0.25,0.16,0.1],[0.25,0.16,0.1],
+                [0.25,0.16,0.1],[0.25,0.16,0.1],[0.25,0.16,0.1],[1.0,1.0,1.0],[1.0,1.0,1.0]],
+            "test" => [[0.0000,0.4627,0.0275],[0.0000,0.3216,0.1176],[0.0000,0.1686,0.2196],[0.0000,0.0392,0.3098],
+                [0.0000,0.0902,0.3961],[0.0000,0.2275,0.4863],[0.0000,0.3804,0.5843],[0.0510,0.5255,0.6863],
+                [0.3137,0.6549,0.7686],[0.5922,0.7961,0.8627],[0.9020,0.9490,0.9647]],
+            "jet" => [[0.0,0.0,0.51],[0.0,0.24,0.67],[0.01,0.49,0.78],[0.01,0.75,0.89],[0.02,1.0,1.0],
+                [0.51,1.0,0.5],[1.0,1.0,0.0],[0.99,0.67,0.0],[0.99,0.33,0.0],[0.98,0.0,0.0],[0.5,0.0,0.0]],
+            _ => return None,
+        };
+        let stops = colors.iter().enumerate().map(|(i, c)| (i as f32 / 10.0, *c)).collect();
+        Some(Self { name: name.to_string(), stops, unit: StopUnit::Normalised, blend: Blend::Smooth, water: None })
+    }
+
+    pub fn load_gdal(path: &Path, blend: Blend) -> Result<Self, String> {
+        //gdaldem color-relief text, one "height r g b [a]" per line with rgb from 0 to 255
+        //heights ending in % are normalised, nv (no data) lines and # comments are skipped
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let name = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut stops = vec![];
+        let mut unit = None;
+        for (number, line) in text.lines().enumerate() {
+            let error = |what: &str| format!("{} line {}: {}", path.display(), number + 1, what);
+            let fields: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',' || c == ':').filter(|f| !f.is_empty()).collect();
+            match fields.first() {
+                None => continue,
+                Some(first) if first.starts_with('#') || first.eq_ignore_ascii_case("nv") => continue,
+                Some(_) => {}
+            }
+            if fields.len() < 4 {
+                return Err(error("expected a height and three colour values"));
+            }
+            let (height, this_unit) = match fields[0].strip_suffix('%') {
+                Some(percent) => (percent.parse::<f32>().map(|p| p / 100.0), StopUnit::Normalised),
+                None => (fields[0].parse::<f32>(), StopUnit::Metres),
+            };
+            let height = height.map_err(|_| error(&format!("'{}' is not a height", fields[0])))?;
+            if *unit.get_or_insert(this_unit) != this_unit {
+                return Err(error("heights must all be in metres or all be percentages"));
+            }
+            let mut colour = [0f32; 3];
+            for (c, field) in colour.iter_mut().zip(&fields[1..4]) {
+                let value: u8 = field.parse().map_err(|_| error(&format!("'{}' is not a colour value from 0 to 255", field)))?;
+                *c = value as f32 / 255.0;
+            }
+            stops.push((height, colour));
+        }
+        Self::new(&name, stops, unit.unwrap_or(StopUnit::Metres), blend).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn stops(&self) -> Vec<[f32; 4]> {
+        //height then rgb for each stop, the vertex shader picks the colour
+        self.stops.iter().map(|(h, [r, g, b])| [*h, *r, *g, *b]).collect()
+    }
+
+    pub fn is_normalised(&self) -> bool {
+        self.unit == StopUnit::Normalised
+    }
+
+    pub fn is_stepped(&self) -> bool {
+        self.blend == Blend::Stepped
+    }
+
+    pub fn water(&self) -> Option<[f32; 3]> {
+        self.water
+    }
+}
+
+pub fn ramps(settings: &Settings) -> Vec<ColorRamp> {
+    //every ramp that can be switched to at runtime, the bands from the settings first
+    let mut ramps = vec![ColorRamp::bands(&settings.colours, &settings.colour_thresholds)];
+    ramps.extend(BUILTIN_RAMPS.iter().filter_map(|name| ColorRamp::builtin(name)));
+    ramps.extend(settings.ramp_files.iter().cloned());
+    ramps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn load(name: &str, text: &str) -> Result<ColorRamp, String> {
+        //load_gdal reads files, so each test writes its own
+        let path = env::temp_dir().join(format!("threedcube-{}-{}.txt", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        let ramp = ColorRamp::load_gdal(&path, Blend::Smooth);
+        fs::remove_file(&path).unwrap();
+        ramp
+    }
+
+    #[test]
+    fn metre_stops_are_sorted_and_scaled() {
+        let ramp = load("metres", "# a comment\n1000 255 255 255\n\n0 0 0 255\n500,0,255,0,128\nnv 0 0 0 0\n").unwrap();
+        assert!(!ramp.is_normalised());
+        assert!(!ramp.is_stepped());
+        assert_eq!(ramp.stops(), vec![[0.0, 0.0, 0.0, 1.0], [500.0, 0.0, 1.0, 0.0], [1000.0, 1.0, 1.0, 1.0]]);
+    }
+
+    #[test]
+    fn percentage_stops_are_normalised() {
+        let ramp = load("percent", "0% 0 0 0\n50% 255 0 0\n100% 255 255 255\n").unwrap();
+        assert!(ramp.is_normalised());
+        assert_eq!(ramp.stops().iter().map(|s| s[0]).collect::<Vec<_>>(), vec![0.0, 0.5, 1.0]);
+        assert!(ramp.name().starts_with("threedcube-percent"));
+    }
+
+    #[test]
+    fn malformed_lines_are_errors_with_their_line() {
+        let err = load("short", "0 0 0 0\n100 255 255\n").unwrap_err();
+        assert!(err.ends_with("line 2: expected a height and three colour values"), "{}", err);
+        let err = load("height", "high 0 0 0\n").unwrap_err();
+        assert!(err.contains("line 1: 'high' is not a height"), "{}", err);
+        let err = load("colour", "0 0 0 0\n100 256 0 0\n").unwrap_err();
+        assert!(err.contains("line 2: '256' is not a colour value"), "{}", err);
+        let err = load("mixed", "0 0 0 0\n50% 255 0 0\n").unwrap_err();
+        assert!(err.contains("line 2: heights must all be in metres or all be percentages"), "{}", err);
+    }
+
+    #[test]
+    fn only_comments_and_no_data_is_empty() {
+        let err = load("empty", "# nothing\nnv 0 0 0\n").unwrap_err();
+        assert!(err.contains("has no stops"), "{}", err);
+    }
+}
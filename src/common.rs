@@ -1,5 +1,5 @@
-use std:: {collections::VecDeque,iter, mem };
-use cgmath::Matrix4;
+use std:: {collections::{HashSet, VecDeque},iter, mem };
+use cgmath::{Matrix4, Vector3};
 use wgpu::util::DeviceExt;
 use winit::{
     event::*,
@@ -16,9 +16,56 @@ use std::time::{Instant, Duration};
 mod transforms;//transforms:: references transforms.rs file
 #[path="surface_data.rs"]
 mod surface;//surface:: references surface.rs file
+#[path="hgt.rs"]
+mod hgt;//hgt:: reads hgt files of any resolution
+#[path="tiles.rs"]
+mod tiles;//tiles:: resolves srtm tile files
+#[path="elevation.rs"]
+mod elevation;//elevation:: sources heights are read from
+#[path="geotiff.rs"]
+mod geotiff;//geotiff:: wgs84 geotiff dems
+#[path="tile_cache.rs"]
+mod tile_cache;//tile_cache:: loads srtm tiles on a pool of worker threads
+#[path="height_sampler.rs"]
+mod height_sampler;//height_sampler:: heights anywhere across tile boundaries
+#[path="void_fill.rs"]
+mod void_fill;//void_fill:: fills holes in the srtm data
+#[path="coords.rs"]
+mod coords;//coords:: render space, sample indices and latitude longitude
+#[path="headless.rs"]
+pub mod headless;//headless:: renders to a png without a window
+#[path="settings.rs"]
+pub mod settings;//settings:: everything chosen at startup
+#[path="cli.rs"]
+pub mod cli;//cli:: command line options into settings
+#[path="config.rs"]
+mod config;//config:: toml settings file
+#[path="keys.rs"]
+mod keys;//keys:: key bindings
+#[path="shader_reload.rs"]
+mod shader_reload;//shader_reload:: reads the shader from disk when it changes
+#[path="sun.rs"]
+mod sun;//sun:: directional light
+#[path="shading.rs"]
+mod shading;//shading:: colour modes and the uniform the fragment shader reads
+#[path="heightmap.rs"]
+mod heightmap;//heightmap:: srtm tiles on the gpu for the vertex shader
+#[path="color_ramp.rs"]
+mod color_ramp;//color_ramp:: colours for heights, built in or from gdal colour relief files
+#[path="lod.rs"]
+mod lod;//lod:: screen space error of a chunk seen from the camera
+#[path="quadtree.rs"]
+mod quadtree;//quadtree:: chunks that get smaller towards the camera
+#[path="camera.rs"]
+mod camera;//camera:: aircraft camera with heading, pitch and roll
+#[path="flight.rs"]
+mod flight;//flight:: point mass flight model the camera rides in
+#[path="ground.rs"]
+mod ground;//ground:: clearance below the camera and the floor that keeps it off the terrain
+#[path="taws.rs"]
+mod taws;//taws:: terrain alerts from the flight path ahead
+use keys::Action;
 
-const X_CHUNKS_COUNT: u32 = 2;
-const Z_CHUNKS_COUNT: u32 = 2;
 
 
 
@@ -56,7 +103,7 @@ struct RenderPipeline<'a> {// render pipeline struct created incase a second ren
         }
     }
 }impl RenderPipeline<'_> {
-    pub fn new(&mut self, init: &WgpuInit) -> wgpu::RenderPipeline {//new render pipeline stuct initialisation
+    pub fn build(&mut self, init: &WgpuInit) -> wgpu::RenderPipeline {//builds a wgpu render pipeline from the struct settings
         if self.shader.is_some() {
             self.vs_shader = self.shader;
             self.fs_shader = self.shader;
@@ -75,14 +122,14 @@ struct RenderPipeline<'a> {// render pipeline struct created incase a second ren
 
         init.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
-            layout: Some(&self.pipeline_layout.unwrap()),
+            layout: Some(self.pipeline_layout.unwrap()),
             vertex: wgpu::VertexState {
-                module: &self.vs_shader.as_ref().unwrap(),
+                module: self.vs_shader.as_ref().unwrap(),
                 entry_point: &self.vs_entry,
-                buffers: &self.vertex_buffer_layout,
+                buffers: self.vertex_buffer_layout,
             },
             fragment: Some(wgpu::FragmentState {
-                module: &self.fs_shader.as_ref().unwrap(),
+                module: self.fs_shader.as_ref().unwrap(),
                 entry_point: &self.fs_entry,
                 targets: &[Some(init.config.format.into())],
             }),
@@ -104,7 +151,8 @@ struct RenderPipeline<'a> {// render pipeline struct created incase a second ren
 
 
 struct WgpuInit {//struct WgpuInit required variables for inialisation of window with WGPU
-    pub surface: wgpu::Surface,
+    pub surface: Option<wgpu::Surface>,//None when rendering offscreen
+    pub offscreen: Option<wgpu::Texture>,//colour target used instead of the surface when there is no window
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
@@ -114,7 +162,7 @@ struct WgpuInit {//struct WgpuInit required variables for inialisation of window
     //implementation of funtions of WgpuInit struct
     pub async fn new(window: &Window, sample_count:u32, limits:Option<wgpu::Limits>) -> Self {
         //new WgpuInitStruct with params
-        let limits_device = limits.unwrap_or(wgpu::Limits::default());
+        let limits_device = limits.unwrap_or_default();
 
         let size = window.inner_size();
         let instance = wgpu::Instance::default();
@@ -159,7 +207,8 @@ struct WgpuInit {//struct WgpuInit required variables for inialisation of window
         surface.configure(&device, &config);
 
         Self {//initialisation of self based on the values determined above
-            surface,
+            surface: Some(surface),
+            offscreen: None,
             device,
             queue,
             config,
@@ -167,6 +216,68 @@ struct WgpuInit {//struct WgpuInit required variables for inialisation of window
             sample_count,
         }
     }
+
+    pub async fn new_offscreen(width: u32, height: u32, sample_count:u32, force_fallback:bool) -> Result<Self, String> {
+        //no window or surface, frames are drawn into a texture that can be copied back to the cpu
+        let instance = wgpu::Instance::default();
+        let mut adapter = None;
+        if !force_fallback {
+            adapter = instance.request_adapter(&wgpu::RequestAdapterOptions::default()).await;
+        }
+        if adapter.is_none() {//a software adapter so it still works without a gpu
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::default(),
+                    compatible_surface: None,
+                    force_fallback_adapter: true,
+                })
+                .await;
+        }
+        let adapter = adapter.ok_or("no graphics adapter found, not even a software one")?;
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                    limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+                },
+                None,
+            )
+            .await
+            .map_err(|e| format!("failed to create device: {}", e))?;
+
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;//matches the byte order png wants
+        let offscreen = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Texture"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        //never used to configure a surface, only kept for the format and size like a window would have
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+
+        Ok(Self {
+            surface: None,
+            offscreen: Some(offscreen),
+            device,
+            queue,
+            config,
+            size: winit::dpi::PhysicalSize::new(width, height),
+            sample_count,
+        })
+    }
 }
 
 
@@ -191,12 +302,12 @@ impl FpsCounter {
         }
     }
 
-    //Updates the fps counter and print fps.
-    pub fn print_fps(&mut self, interval:u64) {
+    //Updates the fps counter and print fps, returns true when it printed.
+    pub fn print_fps(&mut self, interval:u64) -> bool {
         let now = Instant::now();
         let a_second_ago = now - Duration::from_secs(1);
 
-        while self.last_second_frames.front().map_or(false, |t| *t < a_second_ago) {
+        while self.last_second_frames.front().is_some_and(|t| *t < a_second_ago) {
             self.last_second_frames.pop_front();
         }
 
@@ -207,7 +318,26 @@ impl FpsCounter {
             let fps = self.last_second_frames.len();
             println!("FPS: {}", fps);
             self.last_print_time = now;
+            return true;
         }
+        false
+    }
+}
+
+#[derive(Debug, Default)]
+struct FrameClock {//time from one update to the next
+    last: Option<Instant>,
+}
+
+impl FrameClock {
+    const MAX_SECONDS: f32 = 0.25;//a long pause, like dragging the window, is not caught up in one jump
+
+    //Seconds since the last tick, 0 on the first.
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        let seconds = self.last.map_or(0.0, |last| (now - last).as_secs_f32());
+        self.last = Some(now);
+        seconds.min(Self::MAX_SECONDS)
     }
 }
 
@@ -216,82 +346,90 @@ struct State {
     init: WgpuInit,//sturct WgpuInit
     texture_pipeline: wgpu::RenderPipeline,
     pipeline: wgpu::RenderPipeline,//render pipeline
-    vertex_buffer: Vec<wgpu::Buffer>,//vector of vertex buffers its a vector due to the use of chunks
-    vertex_texture_buffer: Vec<wgpu::Buffer>,
+    vertex_buffer: wgpu::Buffer,//grids shared by every chunk, the vertex shader lifts them to the terrain
     index_buffer: wgpu::Buffer,//index buffer
     tex_index_buffer: wgpu::Buffer,
+    grid_levels: Vec<surface::GridLevel>,//where each level of detail's grid is in the index buffers
+    chunks: u32,//quadtree chunks drawn, the first this many in the chunk and model buffers
+    culled: u32,//quadtree chunks left out because they are outside the view
+    max_chunks: u32,//what the chunk and model buffers have room for
+    view_distance: f32,//km
+    pixel_error: f32,//most a chunk may be off the ground on screen before it is split
     uniform_bind_group: wgpu::BindGroup,
     uniform_texture_bind_group: wgpu::BindGroup,
     uniform_buffer: wgpu::Buffer,
+    model_buffer: wgpu::Buffer,//model matrix of every chunk, placing and scaling it
+    chunk_index_buffer: wgpu::Buffer,//one index per chunk stepped per instance, picks the chunk's model matrix
+    heightmap: heightmap::Heightmap,
+    terrain_buffer: wgpu::Buffer,//terrain uniform, rewritten when the camera moves
+    chunk_buffer: wgpu::Buffer,//offset, size and skirt of every chunk, rewritten as the quadtree changes
+    stop_buffer: wgpu::Buffer,//colour ramp stops
+    terrain_bind_group_layout: wgpu::BindGroupLayout,
+    terrain_bind_group: wgpu::BindGroup,
     //view and projection matrix
     view_mat: Matrix4<f32>,
     project_mat: Matrix4<f32>,
     depth_texture_view: wgpu::TextureView,//depth texture
-    index_length: u32,
-    texindex_length: u32,
     plot_type: u32,
-    camera: CamPos,//campos struct for positioning of camera
-    camlook: CamPos,//campos struct for looking direction of camera
-    translations: Vec<[f32; 2]>,
+    camera: camera::Camera,//position, orientation and speed of the camera
+    mouse_held: bool,//right mouse button down, moving the mouse looks around
+    mouse_sensitivity: f32,//degrees per pixel
+    flight: Option<flight::FlightModel>,//the aircraft the camera rides in, none with the free camera
+    aircraft: flight::Aircraft,//flown whenever the pilot key switches to the aircraft
+    ground: ground::GroundGuard,//minimum clearance and the warnings when the camera gets too low
+    taws: taws::Taws,//caution and pull up alerts while flying the aircraft
+    clock: FrameClock,//seconds since the last update, what everything that moves is scaled by
+    held: HashSet<Action>,//movement keys down right now
     terrain: surface::Terrain,//terrain struct initialised from surface_data.rs file
     update_buffers: bool,//update the buffers
     //update_buffers_view: bool, Not used anymore was used to update the view buffer without having to rerender and find the y values of the terrain thought to be more efficient wasnt
     fps_counter: FpsCounter,
+    keys: keys::KeyBindings,
+    shading: shading::Shading,
+    shading_buffer: wgpu::Buffer,
+    lens: transforms::Lens,//field of view and clipping planes
+    pipeline_layout: wgpu::PipelineLayout,//kept so the pipelines can be rebuilt when the shader changes
+    texture_pipeline_layout: wgpu::PipelineLayout,
+    shader_watch: Option<shader_reload::ShaderWatch>,//shader file being watched in dev mode
 }
 impl State {
-    async fn new(
-        window: &Window,
-
-    ) -> Self {
-        let init = WgpuInit::new(&window,1, None).await;
-
-        let shader = init.device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));//attach shader module written in wgsl
+    fn new(init: WgpuInit, settings: &settings::Settings) -> Result<Self, String> {
         //model matrix not needed to be calculated here anymore
         /*let model_mat = transforms::create_transforms(
             [-0.65 * width as f32, 5.0, -0.5 * height as f32],
             [0.0, 0.0, 0.0],
             [1.0, 100.0, 1.0],
         );*/
-        let camera = CamPos{//values added to struct for position of camera
-            x:0.0,
-            y:100.0,
-            z:200.0,
-        };
-        let camlook = CamPos{//values added to struct for looking direction of camera
-            x:0.0,
-            y:100.0,
-            z:-30.0,
-        };
-        let mut terrain = surface::Terrain::default();//calling default function for terrrain struct
-        let mut translations: Vec<[f32; 2]> = vec![];//empty vectors made mut so it can be filled later
-        let mut model_mat: Vec<[f32; 16]> = vec![];
-        let chunk_size1 = (terrain.chunksize - 1) as f32;
-        for i in 0..X_CHUNKS_COUNT {//going through chunks to create the model matrix and the translation vector that will be used later for the positioning of the chunks
-            for j in 0..Z_CHUNKS_COUNT {
-                let xt = -0.5 * X_CHUNKS_COUNT as f32 * chunk_size1 + i as f32 * chunk_size1;
-                let zt = -0.5 * Z_CHUNKS_COUNT as f32 * chunk_size1 + j as f32 * chunk_size1;
-                let translation = [xt, 10.0, zt];
-                let m = transforms::create_transforms(translation, [0.0, 0.0, 0.0], [1.0, 150.0, 1.0]);
-                model_mat.push(*(m.as_ref()));
-                translations.push([xt, zt]);
-            }
+        let camera = camera::Camera::looking_at(settings.camera, settings.camlook, settings.speed);
+        let flight = settings.fly.then(|| flight::FlightModel::new(settings.aircraft.clone(), camera.heading(), camera.pitch(), settings.speed));
+        let source = elevation::open_source(settings.source, settings.data_dir.clone())?;
+        //a heightmap layer is one sample more than the resolution along each side
+        let max_resolution = init.device.limits().max_texture_dimension_2d - 1;
+        let terrain = surface::Terrain::new(settings, source, max_resolution);
+        if terrain.samples_per_degree > max_resolution {
+            eprintln!("tiles have {} samples per degree but this gpu's textures hold at most {}, drawing them at {}",
+                terrain.samples_per_degree, max_resolution + 1, max_resolution);
         }
-        //Model Matrix Storage Buffer initialised
-        let model_storage_buffer = init.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        //Model Matrix Storage Buffer initialised, filled once the quadtree picks the chunks
+        let max_chunks = settings.max_chunks;
+        let model_storage_buffer = init.device.create_buffer(&wgpu::BufferDescriptor {
                     label: Some("Model Matrix Storage Buffer"),
-                    contents: cast_slice(&model_mat),
+                    size: (max_chunks as usize * mem::size_of::<[f32; 16]>()) as wgpu::BufferAddress,
                     usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
                 });
-        //CamPos struct values added to the camera postition and look direction respectively
-        let camera_position = (camera.x, camera.y, camera.z).into();
-        let look_direction = (camlook.x, camlook.y, camlook.z).into();
-        let up_direction = cgmath::Vector3::unit_y();
+        //the camera's position, a point straight ahead of it and its up
+        let (camera_position, look_direction, up_direction) = camera.view_points();
         //Calculation of view matrix projection matrix and viewprojection matrix from transforms.rs file
+        //far always reaches the view distance whatever the spacing of the samples the render units are made of
+        let far = settings.far.max(settings.far_for(terrain.frame().metres_per_sample()));
+        let lens = transforms::Lens { fov: settings.fov.to_radians(), near: settings.near, far };
         let (view_mat, project_mat, vp_mat) = transforms::create_view_projection(
             camera_position,
             look_direction,
             up_direction,
             init.config.width as f32 / init.config.height as f32,
+            &lens,
         );
 
         //let mvp_mat = vp_mat * model_mat; not needed to be calculated anymore here
@@ -304,142 +442,217 @@ impl State {
         });
 
 
+        //sun and colour mode for the fragment shader
+        let shading = shading::Shading::new(settings);
+        let shading_buffer = init.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Shading Uniform Buffer"),
+                    contents: cast_slice(&[shading.uniform(0.0)]),//the altitude is written once the camera is placed
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         //And uniform bind group for vertex shader
         let (vertex_bind_group_layout, vertex_bind_group) = create_bind_group_storage(
             &init.device,
-            vec![wgpu::ShaderStages::VERTEX, wgpu::ShaderStages::VERTEX],
+            vec![wgpu::ShaderStages::VERTEX, wgpu::ShaderStages::VERTEX, wgpu::ShaderStages::FRAGMENT],
             vec![
                 wgpu::BufferBindingType::Uniform,
                 wgpu::BufferBindingType::Storage { read_only: true },
+                wgpu::BufferBindingType::Uniform,
             ],
             &[
                 vertex_uniform_buffer.as_entire_binding(),
                 model_storage_buffer.as_entire_binding(),
+                shading_buffer.as_entire_binding(),
             ],
         );
         let (vertex_texture_bind_group_layout, vertex_texture_bind_group) = create_bind_group_storage(
             &init.device,
-            vec![wgpu::ShaderStages::VERTEX, wgpu::ShaderStages::VERTEX],
+            vec![wgpu::ShaderStages::VERTEX, wgpu::ShaderStages::VERTEX, wgpu::ShaderStages::FRAGMENT],
             vec![
                 wgpu::BufferBindingType::Uniform,
                 wgpu::BufferBindingType::Storage { read_only: true },
+                wgpu::BufferBindingType::Uniform,
             ],
             &[
                 vertex_uniform_buffer.as_entire_binding(),
                 model_storage_buffer.as_entire_binding(),
+                shading_buffer.as_entire_binding(),
             ],
         );
-        //And vertex buffer layout
-        let vertex_buffer_layout = VertexBufferLayout {
-            array_stride: mem::size_of::<surface::Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3], // position and color added to location 0 and 1 respectively (for shader)
-        };
+        //chunk indices go through a vertex buffer rather than instance_index because the gl backend
+        //leaves first_instance out of instance_index, which broke the software adapter used headless
+        let chunk_indices: Vec<u32> = (0..max_chunks).collect();
+        let chunk_index_buffer = init.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Chunk Index Buffer"),
+                    contents: cast_slice(&chunk_indices),
+                    usage: wgpu::BufferUsages::VERTEX,
+        });
+        //heights, the colour ramp and chunk offsets for the vertex shader, tiles are uploaded as they load
+        let heightmap = heightmap::Heightmap::new(&init.device, terrain.resolution());
+        let terrain_buffer = init.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Terrain Uniform Buffer"),
+                    contents: cast_slice(&[terrain.uniform(0)]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let chunk_buffer = init.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Chunk Storage Buffer"),
+                    size: (max_chunks as usize * mem::size_of::<surface::ChunkUniform>()) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+        });
+        //big enough for the ramp with the most stops so switching ramps only writes to it
+        let max_stops = terrain.ramps.iter().map(|r| r.stops().len()).max().unwrap_or(1);
+        let stop_buffer = init.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Colour Ramp Storage Buffer"),
+                    size: (max_stops * mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+        });
+        init.queue.write_buffer(&stop_buffer, 0, cast_slice(&terrain.ramps[terrain.ramp].stops()));
+        let terrain_bind_group_layout = create_terrain_bind_group_layout(&init.device);
+        let terrain_bind_group = create_terrain_bind_group(&init.device, &terrain_bind_group_layout,
+            &[&terrain_buffer, &chunk_buffer, &stop_buffer], &heightmap);
         //Configuring Layout of render pipeline
         let pipeline_layout = init.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&vertex_bind_group_layout],
+                bind_group_layouts: &[&vertex_bind_group_layout, &terrain_bind_group_layout],
                 push_constant_ranges: &[],
             });
-        //Initialised pipeline based on the above layout
-        let mut ppl = RenderPipeline {
-            shader: Some(&shader),
-            pipeline_layout: Some(&pipeline_layout),
-            vertex_buffer_layout: &[vertex_buffer_layout],
-            ..Default::default()
-        };
-        let pipeline = ppl.new(&init);
-        let vertex_texture_buffer_layout = VertexBufferLayout {
-            array_stride: mem::size_of::<surface::Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3], // pos, col
-        };
-
         let pipeline_texture_layout =
             init.device
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                     label: Some("Render Texture Pipeline Layout"),
-                    bind_group_layouts: &[&vertex_texture_bind_group_layout],
+                    bind_group_layouts: &[&vertex_texture_bind_group_layout, &terrain_bind_group_layout],
                     push_constant_ranges: &[],
                 });
-
-        let mut pplt = RenderPipeline {
-            topology: wgpu::PrimitiveTopology::LineList,
-            shader: Some(&shader),
-            pipeline_layout: Some(&pipeline_texture_layout),
-            vertex_buffer_layout: &[vertex_texture_buffer_layout],
-            ..Default::default()
+        //--shader reads the shader from disk and reloads it on change, otherwise the one built in is used
+        let mut shader_watch = settings.shader.as_deref().map(shader_reload::ShaderWatch::new);
+        let from_disk = match &mut shader_watch {
+            Some(watch) => {
+                let source = watch.read()?;
+                shader_reload::compile(&init.device, &source, |shader| create_pipelines(&init, shader, &pipeline_layout, &pipeline_texture_layout))
+                    .map_err(|err| eprintln!("shader {} failed to compile, using the built in one until it is fixed:\n{}", watch.path().display(), err))
+                    .ok()
+            }
+            None => None,
         };
-        let pipeline_texture = pplt.new(&init);
+        let (pipeline, pipeline_texture) = from_disk.unwrap_or_else(|| {
+            let shader = init.device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));//attach shader module written in wgsl
+            create_pipelines(&init, &shader, &pipeline_layout, &pipeline_texture_layout)
+        });
 
 
         let depth_texture_view = create_depth_view(&init);//Creattion o depth texture view no need for multi sample texture view
-        let vertex_data = terrain.create_collection_of_terrain_data(//Calling create.... func from surface_data.rs file with those params
-            X_CHUNKS_COUNT,
-            Z_CHUNKS_COUNT,
-            &translations,
-        );
-        let index_data = terrain.create_indices(vertex_data.2, vertex_data.2);//Calculation of indices
-        let mut vertex_buffer: Vec<wgpu::Buffer> = vec![]; //Mutable vector of vertex buffers created filled below
-        let mut vertex_texture_buffer: Vec<wgpu::Buffer> = vec![];
-        let mut k: usize = 0;
-        for _i in 0..X_CHUNKS_COUNT {//Iterate through all the chunks
-            for _j in 0..Z_CHUNKS_COUNT {
-                let vb = init.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {//retrieve data from vertex data from terrain struct
-                        label: Some("Vertex Buffer"),
-                        contents: cast_slice(&vertex_data.0[k]),
-                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                    });
-                let vtb = init
-                    .device
-                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: Some("Vertex Texture Buffer"),
-                        contents: cast_slice(&vertex_data.1[k]),
-                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                    });
-                vertex_buffer.push(vb);//pushed into vertex vector
-                vertex_texture_buffer.push(vtb);
-                k += 1;
-            }
-        }
+        //every level of detail is built once, r and f pick which range of indices the chunks draw
+        let grids = terrain.create_grids();
+        let vertex_buffer = init.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Grid Vertex Buffer"),
+                contents: cast_slice(&grids.vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
         //index buffer initialised and index data casted to it
         let index_buffer = init.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Index Buffer"),
-                contents: cast_slice(&index_data.0),
-                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                contents: cast_slice(&grids.indices),
+                usage: wgpu::BufferUsages::INDEX,
             });
         let tex_index_buffer = init.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Index Buffer"),
-                contents: cast_slice(&index_data.1),
-                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                contents: cast_slice(&grids.lines),
+                usage: wgpu::BufferUsages::INDEX,
             });
 
 
-        Self {
+        let mut state = Self {
             //Variables initialised above added to State struct
             init,
             pipeline,
             texture_pipeline: pipeline_texture,
             vertex_buffer,
-            vertex_texture_buffer: vertex_texture_buffer,
             index_buffer,
-            tex_index_buffer: tex_index_buffer,
+            tex_index_buffer,
+            grid_levels: grids.levels,
+            chunks: 0,
+            culled: 0,
+            max_chunks,
+            view_distance: settings.view_distance,
+            pixel_error: settings.pixel_error,
             uniform_bind_group: vertex_bind_group,
             uniform_texture_bind_group: vertex_texture_bind_group,
             uniform_buffer:vertex_uniform_buffer,
+            model_buffer: model_storage_buffer,
+            chunk_index_buffer,
+            heightmap,
+            terrain_buffer,
+            chunk_buffer,
+            stop_buffer,
+            terrain_bind_group_layout,
+            terrain_bind_group,
             view_mat,
             project_mat,
             depth_texture_view,
-            index_length: index_data.0.len() as u32,
-            texindex_length: index_data.1.len() as u32,
             camera,
-            camlook,
-            translations,
+            mouse_held: false,
+            mouse_sensitivity: settings.mouse_sensitivity,
+            flight,
+            aircraft: settings.aircraft.clone(),
+            ground: ground::GroundGuard::new(settings.min_clearance, settings.floor),
+            taws: taws::Taws::new(settings.taws_alerts),
+            clock: FrameClock::default(),
+            held: HashSet::new(),
             terrain,
             plot_type: 0,
             update_buffers: false,
             //update_buffers_view: false,
             fps_counter: FpsCounter::default(),
+            keys: settings.keys.clone(),
+            shading,
+            shading_buffer,
+            lens,
+            pipeline_layout,
+            texture_pipeline_layout: pipeline_texture_layout,
+            shader_watch,
+        };
+        state.place_camera(settings.start);
+        state.write_shading();
+        state.terrain.level_of_detail = settings.level_of_detail;
+        Ok(state)
+    }
+
+    fn place_camera(&mut self, point: coords::GeoPoint) {
+        //move the terrain so the point is directly below the camera
+        self.terrain.place_at(point);
+        let frame = self.terrain.frame();
+        let (sx, sz) = frame.render_to_sample(self.camera.position.x, self.camera.position.z);
+        self.terrain.moves[0] -= (sx - frame.moves[0] as f64).round() as f32;
+        self.terrain.moves[1] -= (sz - frame.moves[1] as f64).round() as f32;
+        self.update_buffers = true;
+    }
+
+    fn position(&mut self) -> (coords::GeoPoint, f32, Option<f32>) {
+        //where the camera is, its altitude in metres and the ground height below it
+        let frame = self.terrain.frame();
+        let (lat, long) = frame.render_to_geo(self.camera.position.x, self.camera.position.z);
+        let altitude = frame.render_y_to_metres(self.camera.position.y);
+        (coords::GeoPoint { lat, long }, altitude, self.terrain.height_at(lat, long))
+    }
+
+    fn check_ground(&mut self) {
+        //keep the camera above the floor and warn the first time it gets too low, water counts as ground
+        let (_, altitude, ground) = self.position();
+        let Some(ground) = ground else { return };//tiles still loading, nothing to check against
+        let (allowed, event) = self.ground.check(altitude, ground.max(self.terrain.water_level));
+        match event {
+            Some(ground::GroundEvent::LowClearance { clearance }) => println!("WARNING: terrain {:.0} m below", clearance),
+            Some(ground::GroundEvent::Collision { depth }) => println!("WARNING: collision with the terrain, {:.0} m into it", depth),
+            None => {}
+        }
+        if allowed != altitude {
+            self.camera.position.y = self.terrain.frame().metres_to_render_y(allowed);
+            if let Some(flight) = &mut self.flight {
+                flight.touch_down();
+            }
+            self.camera_moved();
         }
     }
 
@@ -448,75 +661,190 @@ impl State {
             self.init.size = new_size;
             self.init.config.width = new_size.width;
             self.init.config.height = new_size.height;
-            self.init.surface.configure(&self.init.device, &self.init.config);
+            if let Some(surface) = &self.init.surface {
+                surface.configure(&self.init.device, &self.init.config);
+            }
             //Determines the new sizes recalculates the matrixes needed for viewing and updates the buffer
-            self.project_mat = transforms::create_projection(new_size.width as f32 / new_size.height as f32, true);
+            self.project_mat = transforms::create_projection(new_size.width as f32 / new_size.height as f32, true, &self.lens);
             let vp_mat = self.project_mat * self.view_mat;
             self.init.queue.write_buffer(&self.uniform_buffer, 0, cast_slice(vp_mat.as_ref() as &[f32; 16]), );
             self.depth_texture_view = create_depth_view(&self.init);
+            self.update_buffers = true;//the view's shape decides which chunks are culled
         }
     }
 
-    pub fn plane_move(&mut self, moves: char) {//Plane moving function called from input
-        match moves {
-            'e' => {//North south west and east depending on direction also moves camera to face the moving direction
-                let increment_count = if self.terrain.level_of_detail <= 5 { self.terrain.level_of_detail + 1} else { 2*(self.terrain.level_of_detail - 2)};
-                    self.terrain.moves[0] += increment_count as f32;
-                    self.update_buffers = true;
-            },
-            'w' => {
-                    let increment_count = if self.terrain.level_of_detail <= 5 { self.terrain.level_of_detail + 1} else { 2*(self.terrain.level_of_detail - 2)};
-                    self.terrain.moves[0] -= increment_count as f32;
-                    self.update_buffers = true;
+    fn fly(&mut self, seconds: f32) {
+        //move along the heading at the camera's speed
+        let units = self.camera.speed * seconds / self.terrain.frame().metres_per_sample() as f32;
+        self.shift(self.camera.forward() * units);
+    }
 
-            },
-            's' => {
-                let increment_count = if self.terrain.level_of_detail <= 5 { self.terrain.level_of_detail + 1} else { 2*(self.terrain.level_of_detail - 2)};
-                    self.terrain.moves[1] += increment_count as f32;
-                    self.update_buffers = true;
+    fn shift(&mut self, step: Vector3<f32>) {
+        //move the camera in render units, the terrain slides underneath so the camera stays over the middle of the chunks
+        let (sx, sz) = self.terrain.frame().render_to_sample(step.x, step.z);
+        self.terrain.moves[0] = sx as f32;
+        self.terrain.moves[1] = sz as f32;
+        self.camera.position.y += step.y;
+        self.camera_moved();
+    }
 
-                },
-            'n' =>{
-                let increment_count = if self.terrain.level_of_detail <= 5 { self.terrain.level_of_detail + 1} else { 2*(self.terrain.level_of_detail - 2)};
-                    self.terrain.moves[1] -= increment_count as f32;
-                    self.update_buffers = true;
+    fn fly_aircraft(&mut self, seconds: f32) {
+        //step the flight model through the time since the last frame and carry the camera with it
+        let Some(flight) = &mut self.flight else { return };
+        let ([east, up, north], event) = flight.advance(seconds);
+        match event {
+            Some(flight::StallEvent::Stalled) => println!("STALL"),
+            Some(flight::StallEvent::Recovered) => println!("Stall recovered"),
+            None => {}
+        }
+        if [east, up, north] == [0.0; 3] {
+            return;//no whole step yet, the view stays as it is
+        }
+        self.camera.speed = flight.airspeed;
+        self.camera.set_attitude(flight.heading, flight.flight_path, flight.bank);
+        let frame = self.terrain.frame();
+        let per_metre = 1.0 / frame.metres_per_sample() as f32;
+        self.shift(Vector3::new(east * per_metre, up * frame.vertical_scale, -north * per_metre));
+    }
 
-            },
-            'u' => {//Change y level completely
-                self.camera.y = self.camera.y+1.0;
-                self.camlook.y = self.camlook.y+1.0;
-                self.update_buffers = true;
+    fn check_terrain_ahead(&mut self, seconds: f32) {
+        //taws alerts from where the aircraft is heading, the free camera has no flight path to look along
+        let Some(flight) = &self.flight else { return };
+        if seconds == 0.0 {
+            return;
+        }
+        let frame = self.terrain.frame();
+        let state = taws::FlightState {
+            altitude: frame.render_y_to_metres(self.camera.position.y),
+            ground_speed: flight.ground_speed(),
+            vertical_speed: flight.climb_rate(),
+            heading: flight.heading,
+            climb_gradient: flight.climb_gradient(),
+        };
+        let (x, z) = (self.camera.position.x, self.camera.position.z);
+        let per_metre = 1.0 / frame.metres_per_sample() as f32;
+        let terrain = &mut self.terrain;
+        let water_level = terrain.water_level;
+        let mut ahead = |east: f32, north: f32| {
+            let (lat, long) = frame.render_to_geo(x + east * per_metre, z - north * per_metre);
+            terrain.height_at(lat, long).map(|height| height.max(water_level))
+        };
+        if let Some(alert) = self.taws.update(&state, &mut ahead, seconds) {
+            println!("{}", alert);
+        }
+    }
 
-            },
-            'd' => {
-                self.camera.y = self.camera.y-1.0;
-                self.camlook.y = self.camlook.y-1.0;
-                self.update_buffers = true;
-
-            },//or just the direction the camera is looking at
-            'q' => self.camlook.y = self.camlook.y+1.0,
-            'z' => self.camlook.y = self.camlook.y-1.0,
-            'c' => self.update_buffers = true,
+    fn switch_pilot(&mut self) {
+        //hand the camera to the aircraft, starting from where the camera points, or back to the free camera
+        self.flight = match self.flight.take() {
+            Some(flight) => {
+                println!("Free camera");
+                self.taws.reset();
+                self.camera.speed = flight.airspeed;
+                None
+            }
+            None => {
+                println!("Flying the {}", self.aircraft.name);
+                Some(flight::FlightModel::new(self.aircraft.clone(), self.camera.heading(), self.camera.pitch(), self.camera.speed))
+            }
+        };
+        self.camera_moved();
+    }
+
+    fn hold_keys(&mut self, seconds: f32) {
+        //held keys move for as long as they are down, so how far does not depend on the frame rate or key repeat
+        if self.held.is_empty() {
+            return;
+        }
+        let axis = |more: Action, less: Action| self.held.contains(&more) as i32 as f32 - self.held.contains(&less) as i32 as f32;
+        let forward = axis(Action::Forward, Action::Back);
+        let climb = axis(Action::Up, Action::Down);
+        let faster = axis(Action::Faster, Action::Slower);
+        let (yaw, pitch, roll) = (axis(Action::YawRight, Action::YawLeft), axis(Action::PitchUp, Action::PitchDown), axis(Action::RollRight, Action::RollLeft));
+        match &mut self.flight {
+            Some(flight) => {
+                //flying the keys move the controls, forward and back work the throttle too
+                flight.change_throttle((forward + faster).clamp(-1.0, 1.0) * flight::THROTTLE_PER_SECOND * seconds);
+                flight.change_pitch(pitch * flight::PITCH_PER_SECOND * seconds);
+                flight.change_bank(roll * flight::BANK_PER_SECOND * seconds);
+                flight.yaw(yaw * flight::YAW_PER_SECOND * seconds);
+            }
+            None => {
+                self.camera.speed *= camera::SPEED_RATE.powf(faster * seconds);
+                if (yaw, pitch, roll) != (0.0, 0.0, 0.0) {
+                    let turn = camera::TURN_RATE * seconds;
+                    self.turn(yaw * turn, pitch * turn, roll * turn);
+                }
+                if forward != 0.0 {
+                    self.fly(forward * seconds);
+                }
+                if climb != 0.0 {//straight up or down at the camera's speed
+                    let rise = climb * self.camera.speed * seconds * self.terrain.frame().vertical_scale;
+                    self.shift(Vector3::new(0.0, rise, 0.0));
+                }
+            }
+        }
+    }
+
+    fn report_speed(&self, released: Action) {
+        //once a speed key is let go rather than every frame it is held
+        match (&self.flight, released) {
+            (Some(flight), Action::Forward | Action::Back | Action::Faster | Action::Slower) => println!("Throttle: {:.0}%", flight.throttle * 100.0),
+            (None, Action::Faster | Action::Slower) => println!("Speed: {:.0} m/s", self.camera.speed),
             _ => {}
         }
-        let look_direction = (self.camlook.x,self.camlook.y,self.camlook.z).into();
-        let up_direction = cgmath::Vector3::unit_y();
+    }
 
-        let camera_position = (self.camera.x, self.camera.y, self.camera.z).into();
-        //Looking direction and camera position recalculated
-        let (view_mat, project_mat, _vp_mat) = transforms::create_view_projection(
-            camera_position,
-            look_direction,
-            up_direction,
-            self.init.config.width as f32 / self.init.config.height as f32,
-        );//Update the view and projection matries _vp_mat unused thats why it starts with _
+    fn turn(&mut self, yaw: f32, pitch: f32, roll: f32) {
+        self.camera.rotate(yaw, pitch, roll);
+        self.camera_moved();
+    }
+
+    fn mouse_look(&mut self, delta: (f64, f64)) {
+        //only while the right mouse button is held so the mouse is still free for the window otherwise
+        //flying, the mouse is the control column and moves the bank and pitch the aircraft is asked for
+        if self.mouse_held {
+            let sensitivity = self.mouse_sensitivity;
+            match &mut self.flight {
+                Some(flight) => {
+                    flight.change_bank(delta.0 as f32 * sensitivity);
+                    flight.change_pitch(-delta.1 as f32 * sensitivity);
+                }
+                None => {
+                    self.camera.look(delta.0 as f32 * sensitivity, -delta.1 as f32 * sensitivity);
+                    self.camera_moved();
+                }
+            }
+        }
+    }
 
-        self.view_mat=view_mat;
-        self.project_mat=project_mat;
+    fn camera_moved(&mut self) {
+        //view matrix from the camera, the buffers are written in update
+        let (eye, ahead, up) = self.camera.view_points();
+        self.view_mat = Matrix4::look_at_rh(eye, ahead, up);
+        self.update_buffers = true;
     }
+
     fn input(&mut self, event: &WindowEvent) -> bool {
         //Match key inputs to the appropriate effects
         match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { virtual_keycode: Some(keycode), state, .. },
+                ..
+            } if self.keys.action(*keycode).is_some_and(Action::is_held) => {
+                //movement keys are only remembered here, hold_keys acts on them every frame
+                let action = self.keys.action(*keycode).expect("checked in the guard");
+                match state {
+                    ElementState::Pressed => {
+                        self.held.insert(action);
+                    }
+                    ElementState::Released => {
+                        self.held.remove(&action);
+                        self.report_speed(action);
+                    }
+                }
+                true
+            }
             WindowEvent::KeyboardInput {
                 input:
                 KeyboardInput {
@@ -525,264 +853,230 @@ impl State {
                     ..
                 },
                 ..
-            } => match keycode {
-                VirtualKeyCode::W => {//Move north with w
-                    self.plane_move('n');
-                    true
-                }
-                VirtualKeyCode::S => {//Move south with s
-                    self.plane_move('s');
+            } => match self.keys.action(*keycode) {//keys can be rebound in the config file
+                Some(Action::Pilot) => {//Switch between the aircraft and the free camera, p by default
+                    self.switch_pilot();
                     true
                 }
-                VirtualKeyCode::Space => {
+                Some(Action::PlotType) => {
                     self.plot_type = (self.plot_type + 1) % 2;
                     self.update_buffers = true;
                     true
                 }
-                VirtualKeyCode::A => {//Move west with a
-                    self.plane_move('w');
-                    true
-                }
-                VirtualKeyCode::D => {//Move east with d
-                    self.plane_move('e');
-                    true
-                }
-                VirtualKeyCode::PageUp => {//Plane goes up
-                    self.plane_move('u');
-                    true
-                }
-                VirtualKeyCode::PageDown => {//Plane goes down
-                    self.plane_move('d');
-                    true
-                }VirtualKeyCode::Right => {//Plane looks right
-
-                        if self.camera.x > -160.0 {
-                            self.camera.x -=3.0
-                        }
-                        if self.camera.z > 0.0 {
-                            if self.camera.z < 3.0{
-                            self.camera.z -=1.0
-                            }else {
-                                self.camera.z -= 3.0
-                            }
-                        }
-                        if self.camera.z < 0.0 {
-                            if self.camera.z > -30.0{
-                            self.camera.z +=1.0
-                            }else {
-                                self.camera.z += 3.0
-                            }
+                Some(Action::Level) => {//Wings level and the nose on the horizon
+                    match &mut self.flight {
+                        Some(flight) => flight.level(),
+                        None => {
+                            self.camera.level();
+                            self.camera_moved();
                         }
-                    if self.camlook.x < 200.0 {
-                        self.camlook.x += 3.0;
                     }
-                    self.plane_move('c');
                     true
                 }
-                VirtualKeyCode::Left => {//Plane looks left
-
-                        if self.camera.x < 160.0 {
-                            self.camera.x +=3.0
-                        }
-                        if self.camera.z > 0.0 {
-                            if self.camera.z < 3.0{
-                            self.camera.z -=1.0
-                            }else {
-                                self.camera.z -= 3.0
-                            }
-                        }
-                        if self.camera.z < 0.0 {
-                            if self.camera.z > -3.0{
-                            self.camera.z +=1.0
-                            }else {
-                                self.camera.z += 3.0
-                            }
-                        }
-                    if self.camlook.x > -200.0 {
-                        self.camlook.x -= 3.0;
+                Some(Action::LessDetail) => {//Decrease level of detail increase performance
+                    if (self.terrain.level_of_detail as usize) < self.terrain.lod_steps.len() - 1 {
+                        self.terrain.level_of_detail += 1;
                     }
-                    self.plane_move('c');
+                    self.update_buffers = true;
                     true
                 }
-                VirtualKeyCode::Down => {//Plane looks back
-                    if self.camera.z > -200.0 {
-                            self.camera.z -=3.0
-                        }
-                        if self.camera.x > 0.0 {
-                            if self.camera.x < 3.0{
-                            self.camera.x -=1.0
-                            }else {
-                                self.camera.x -= 3.0
-                            }
-                        }
-                        if self.camera.x < 0.0 {
-                            if self.camera.x > -30.0{
-                            self.camera.x +=1.0
-                            }else {
-                                self.camera.x += 3.0
-                            }
-                        }
-                    if self.camlook.z == 30.0{if self.camlook.x<0.0 {
-                        if self.camlook.x < -10.0 { self.camlook.x += 3.0; }
-                        self.camlook.x += 1.0;
-                    }
-                    if self.camlook.x>0.0 {
-                        if self.camlook.x > 10.0 { self.camlook.x -= 3.0; }
-                        self.camlook.x -= 1.0;
-                    }}
-                    if self.camlook.z == -30.0{
-                    if self.camlook.x<=0.0 {
-                    if self.camlook.x > -120.0 {
-                        self.camlook.x -= 3.0;
+                Some(Action::MoreDetail) => {//Increase level of detail decrease performance
+                    if self.terrain.level_of_detail >0 {
+                        self.terrain.level_of_detail -= 1;
                     }
-                    if self.camlook.x < -119.0 {
-                        self.camlook.z = 30.0;
-                        self.camlook.x = -120.0;
-                    }}if self.camlook.x>0.0 {
-                        if self.camlook.x < 120.0 {
-                            self.camlook.x += 3.0;
-                        }
-                        if self.camlook.x > 119.0 {
-                            self.camlook.z = 30.0;
-                            self.camlook.x = 120.0;
-                        }
-                    }}
-                    self.plane_move('c');
+                    self.update_buffers = true;
                     true
                 }
-                VirtualKeyCode::Up => {//Plane looks straight
-                    if self.camera.z < 200.0 {
-                            self.camera.z +=3.0
-                        }
-                        if self.camera.x > 0.0 {
-                            if self.camera.x < 3.0{
-                            self.camera.x -=1.0
-                            }else {
-                                self.camera.x -= 3.0
-                            }
-                        }
-                        if self.camera.x < 0.0 {
-                            if self.camera.x > -30.0{
-                            self.camera.x +=1.0
-                            }else {
-                                self.camera.x += 3.0
-                            }
-                        }
-                    if self.camlook.z == 30.0{
-                        if self.camlook.x<=0.0 {
-                            if self.camlook.x > -120.0 {
-                                self.camlook.x -= 3.0;
-                            }
-                        if self.camlook.x < -119.0{
-                            self.camlook.z = -30.0;
-                            self.camlook.x =-120.0;
-                        }}if self.camlook.x>0.0 {
-                            if self.camlook.x < 120.0 {
-                                self.camlook.x += 3.0;
-                        }
-                        if self.camlook.x > 119.0 {
-                            self.camlook.z = -30.0;
-                            self.camlook.x = 120.0;
-                        }
-                        }}
-                        if self.camlook.z == -30.0{
-                        if self.camlook.x<0.0{
-                        if self.camlook.x< -10.0{self.camlook.x += 3.0;}
-                        self.camlook.x += 1.0;
-                        }
-                        if self.camlook.x>0.0{
-                        if self.camlook.x> 10.0{self.camlook.x -= 3.0;}
-                            self.camlook.x -= 1.0;
-                        }
-                        }
-                    self.plane_move('c');
+                Some(Action::Interpolation) => {//Switch between bilinear and bicubic height sampling
+                    self.terrain.interpolation = match self.terrain.interpolation {
+                        height_sampler::Interpolation::Bilinear => height_sampler::Interpolation::Bicubic,
+                        height_sampler::Interpolation::Bicubic => height_sampler::Interpolation::Bilinear,
+                    };
+                    println!("Height sampling: {:?}", self.terrain.interpolation);
                     true
                 }
-                VirtualKeyCode::Q => {//Look up
-                    self.plane_move('q');
+                Some(Action::Voids) => {//Highlight filled in srtm voids
+                    self.terrain.show_voids = !self.terrain.show_voids;
                     self.update_buffers = true;
                     true
                 }
-                VirtualKeyCode::E => {//Look down
-                    self.plane_move('z');
+                Some(Action::Minimise) => {//Increase level of detail decrease performance
+                    self.terrain.minimised = !self.terrain.minimised;
                     self.update_buffers = true;
                     true
                 }
-                VirtualKeyCode::R => {//Decrease level of detail increase performance
-                    if self.terrain.level_of_detail <7 {
-                        self.terrain.level_of_detail += 1;
-                    }
-                    self.update_buffers = true;
+                Some(Action::SunLeft) => {//Sun moves anticlockwise
+                    self.move_sun(-sun::SUN_STEP, 0.0);
                     true
                 }
-                VirtualKeyCode::F => {//Increase level of detail decrease performance
-                    if self.terrain.level_of_detail >0 {
-                        self.terrain.level_of_detail -= 1;
-                    }
-                    self.update_buffers = true;
+                Some(Action::SunRight) => {//Sun moves clockwise
+                    self.move_sun(sun::SUN_STEP, 0.0);
                     true
                 }
-                VirtualKeyCode::LControl => {//Increase level of detail decrease performance
-                    if self.terrain.minimised == true {
-                        self.terrain.minimised = false;
-                    }else{
-                        self.terrain.minimised = true;
-                    }
+                Some(Action::SunUp) => {
+                    self.move_sun(0.0, sun::SUN_STEP);
+                    true
+                }
+                Some(Action::SunDown) => {
+                    self.move_sun(0.0, -sun::SUN_STEP);
+                    true
+                }
+                Some(Action::ColourMode) => {//Cycle through elevation, hillshade, slope, aspect and tinted colouring
+                    self.shading.mode = self.shading.mode.next();
+                    self.write_shading();
+                    println!("Colour mode: {}", self.shading.mode);
+                    true
+                }
+                Some(Action::ColourRamp) => {//Switch to the next colour ramp
+                    println!("Colour ramp: {}", self.terrain.next_ramp());
+                    self.init.queue.write_buffer(&self.stop_buffer, 0, cast_slice(&self.terrain.ramps[self.terrain.ramp].stops()));
                     self.update_buffers = true;
                     true
                 }
-                _ => false,
+                Some(_) | None => false,//held keys are dealt with above
             },
+            WindowEvent::Focused(false) => {//keys let go of in another window never send their release here
+                self.held.clear();
+                false
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Right, .. } => {
+                self.mouse_held = *state == ElementState::Pressed;
+                true
+            }
             _ => false,
         }
     }
 
+    fn move_sun(&mut self, azimuth: f32, elevation: f32) {
+        //only the sun uniform changes, the terrain does not need rebuilding
+        self.shading.sun.turn(azimuth);
+        self.shading.sun.raise(elevation);
+        self.write_shading();
+        println!("Sun: azimuth {:.0} elevation {:.0}", self.shading.sun.azimuth, self.shading.sun.elevation);
+    }
+
+    fn write_shading(&self) {
+        //taws colours follow the camera altitude so this is written whenever the camera moves too
+        let altitude = self.terrain.frame().render_y_to_metres(self.camera.position.y);
+        self.init.queue.write_buffer(&self.shading_buffer, 0, cast_slice(&[self.shading.uniform(altitude)]));
+    }
+
+    fn reload_shader(&mut self) {
+        //rebuild both pipelines when the watched shader file changes, a broken shader keeps the old pipelines
+        let Some(watch) = &mut self.shader_watch else { return };
+        let Some(source) = watch.poll() else { return };
+        let path = watch.path().display().to_string();
+        let built = source.and_then(|source| shader_reload::compile(&self.init.device, &source, |shader| {
+            create_pipelines(&self.init, shader, &self.pipeline_layout, &self.texture_pipeline_layout)
+        }));
+        match built {
+            Ok((pipeline, texture_pipeline)) => {
+                self.pipeline = pipeline;
+                self.texture_pipeline = texture_pipeline;
+                println!("reloaded shader {}", path);
+            }
+            Err(err) => eprintln!("shader {} failed to compile, keeping the previous one:\n{}", path, err),
+        }
+    }
+
+    fn select_chunks(&mut self) {
+        //split the quadtree around the camera until no chunk's error shows by more than pixel_error pixels
+        let frame = self.terrain.frame();
+        let moves = self.terrain.render_moves();
+        let east_west = frame.east_west_scale();
+        let chunk_span = self.terrain.chunksize - 1;
+        let pixels_per_unit = lod::pixels_per_unit(self.init.config.height, self.lens.fov);
+        let (camera, pixel_error) = (self.camera.position, self.pixel_error);
+        //the view distance in samples, which are closer together east to west
+        let radius = self.view_distance as f64 * 1000.0 / frame.metres_per_sample();
+        let centre = [moves[0] as f64 + (camera.x / east_west) as f64, moves[1] as f64 + camera.z as f64];
+        let roots = quadtree::roots(centre, [radius / east_west as f64, radius], chunk_span);
+        let offset = |node: quadtree::Node| [node.x as f32 - moves[0], node.z as f32 - moves[1]];
+        let frustum = transforms::Frustum::from_view_projection(&(self.project_mat * self.view_mat));
+        let terrain = &mut self.terrain;
+        let nodes = quadtree::select(roots, chunk_span, self.max_chunks as usize, |node| {
+            let measure = terrain.measure(node);
+            let heights = (measure.min_height, measure.max_height);
+            let bounds = lod::chunk_bounds(offset(node), node.span(chunk_span) as f32, heights, &frame);
+            //chunks out of view are never split so the budget goes on what can be seen
+            frustum.intersects_box(bounds.min, bounds.max)
+                && lod::too_coarse(measure.error * frame.vertical_scale, bounds.distance_to(camera), pixels_per_unit, pixel_error)
+        });
+        let increment = terrain.increment_count();
+        let chunks: Vec<surface::ChunkUniform> = nodes.iter().filter_map(|&node| {
+            //a skirt as deep as the chunk's height range covers the gap to a neighbour of any level
+            let measure = terrain.measure(node);
+            let skirt_depth = measure.max_height - measure.min_height + 1.0;
+            let heights = (measure.min_height - skirt_depth, measure.max_height);
+            let bounds = lod::chunk_bounds(offset(node), node.span(chunk_span) as f32, heights, &frame);
+            frustum.intersects_box(bounds.min, bounds.max)
+                .then(|| surface::ChunkUniform::new([node.x as i32, node.z as i32], increment, node.scale(), skirt_depth))
+        }).collect();
+        self.init.queue.write_buffer(&self.chunk_buffer, 0, cast_slice(&chunks));
+        self.init.queue.write_buffer(&self.model_buffer, 0, cast_slice(&create_model_matrices(&chunks, moves, &frame)));
+        self.chunks = chunks.len() as u32;
+        self.culled = (nodes.len() - chunks.len()) as u32;
+    }
+
     fn update(&mut self) {
+        self.reload_shader();
+        let seconds = self.clock.tick();
+        self.hold_keys(seconds);
+        self.fly_aircraft(seconds);
+        self.check_terrain_ahead(seconds);
+        if self.terrain.poll_tiles() {//rebuild the terrain once tiles finish loading in the background
+            self.update_buffers = true;
+        }
         // update buffers:
         if self.update_buffers {
-            //Recalculate vertex data
-            let vertex_data = self.terrain.create_collection_of_terrain_data(
-            X_CHUNKS_COUNT,
-            Z_CHUNKS_COUNT,
-            &self.translations,
-        );
-            let mut k = 0usize;
-            //Iterate through all chunks and add vertex data to the buffer
-            for _i in 0..X_CHUNKS_COUNT {
-                for _j in 0..Z_CHUNKS_COUNT {
-                    self.init.queue.write_buffer(
-                        &self.vertex_buffer[k],
-                        0,
-                        cast_slice(&vertex_data.0[k]),
-                    );
-                    self.init.queue.write_buffer(
-                        &self.vertex_texture_buffer[k],
-                        0,
-                        cast_slice(&vertex_data.1[k]),
-                    );
-                    k += 1;
-                }
-            }//re calculate view projection matrix
+            self.check_ground();//the ground below only changes as the camera moves or tiles arrive
+            //minimising changes the resolution so the heightmap is made again at the new size
+            if self.heightmap.resolution() != self.terrain.resolution() {
+                self.heightmap = heightmap::Heightmap::new(&self.init.device, self.terrain.resolution());
+                self.terrain_bind_group = create_terrain_bind_group(&self.init.device, &self.terrain_bind_group_layout,
+                    &[&self.terrain_buffer, &self.chunk_buffer, &self.stop_buffer], &self.heightmap);
+            }
+            //only tiles the gpu does not have yet are copied, moving around just rewrites the uniforms
+            let loaded = self.heightmap.upload(&self.init.queue, &self.terrain.neighbourhood());
+            self.init.queue.write_buffer(&self.terrain_buffer, 0, cast_slice(&[self.terrain.uniform(loaded)]));
+            self.select_chunks();
+            //re calculate view projection matrix
             let vp_mat = self.project_mat * self.view_mat;
             self.init.queue.write_buffer(&self.uniform_buffer, 0, cast_slice(vp_mat.as_ref() as &[f32; 16]), );
-            //update index data and write to buffer
-            let index_data = self.terrain.create_indices(vertex_data.2, vertex_data.2);
-            self.init.queue.write_buffer(&self.index_buffer, 0, cast_slice(&index_data.0));
-            self.init.queue.write_buffer(&self.tex_index_buffer, 0, cast_slice(&index_data.1));
-            self.index_length = index_data.0.len() as u32;
-            self.texindex_length = index_data.1.len() as u32;
+            self.write_shading();
             self.update_buffers = false;
         }
     }
 
      fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         //Render pass renders all data from the buffers
-        let output = self.init.surface.get_current_texture()?;
+        let output = self.init.surface.as_ref().expect("render needs a window, use draw offscreen").get_current_texture()?;
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.draw(&view);
+        if self.fps_counter.print_fps(5) {//report where the camera is alongside the fps
+            let (point, altitude, ground) = self.position();
+            match ground {
+                Some(height) => println!("Position: {:.5} {:.5} altitude {:.0} m ground {:.1} m", point.lat, point.long, altitude, height),
+                None => println!("Position: {:.5} {:.5} altitude {:.0} m ground loading", point.lat, point.long, altitude),
+            }
+            println!("Attitude: heading {:.0} pitch {:.0} roll {:.0} speed {:.0} m/s",
+                self.camera.heading(), self.camera.pitch(), self.camera.roll(), self.camera.speed);
+            if let Some(flight) = &self.flight {
+                println!("Aircraft: {} throttle {:.0}% airspeed {:.0} m/s climb {:.1} m/s{} {}", flight.aircraft().name,
+                    flight.throttle * 100.0, flight.airspeed, flight.climb_rate(), if flight.stalled { " STALLED" } else { "" }, self.taws.alert());
+            }
+            println!("Chunks: {} drawn {} culled", self.chunks, self.culled);
+        }
+        output.present();
 
+        Ok(())
+    }
+
+    fn draw(&mut self, view: &wgpu::TextureView) {
+        //draws one frame into any colour target, the window surface or an offscreen texture
         let mut encoder = self.init.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Render Encoder"), });
         {
-            let color_attach = create_color_attachment(&view);
+            let color_attach = create_color_attachment(view);
             let depth_attachment = create_depth_stencil_attachment(&self.depth_texture_view);
 
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -796,48 +1090,76 @@ impl State {
             }else{
                 "both"
             };
-             if plot_type == "shape" || plot_type == "both" {
-                 render_pass.set_pipeline(&self.pipeline);
-                 render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-                 let mut k: u32 = 0;
-                 for _i in 0..X_CHUNKS_COUNT {
-                     for _j in 0..Z_CHUNKS_COUNT {
-                         render_pass.set_vertex_buffer(0, self.vertex_buffer[k as usize].slice(..));
-                         render_pass.set_index_buffer(
-                             self.index_buffer.slice(..),
-                             wgpu::IndexFormat::Uint32,
-                         );
-                         render_pass.draw_indexed(0..self.index_length, 0, k..k + 1);
-                         k += 1;
-                     }
-                 }
-             }
+            //every chunk draws the same grid, the chunk index picks its model matrix and offset
+            let grid = &self.grid_levels[self.terrain.level_of_detail as usize];
+            if plot_type == "shape" || plot_type == "both" {
+                render_pass.set_pipeline(&self.pipeline);
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.terrain_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(1, self.chunk_index_buffer.slice(..));
+                render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(grid.indices.clone(), 0, 0..self.chunks);
+            }
             if plot_type == "both" {
                 render_pass.set_pipeline(&self.texture_pipeline);
                 render_pass.set_bind_group(0, &self.uniform_texture_bind_group, &[]);
-
-                let mut k: u32 = 0;
-                for _i in 0..X_CHUNKS_COUNT {
-                    for _j in 0..Z_CHUNKS_COUNT {
-                        render_pass
-                            .set_vertex_buffer(0, self.vertex_texture_buffer[k as usize].slice(..));
-                        render_pass.set_index_buffer(
-                            self.tex_index_buffer.slice(..),
-                            wgpu::IndexFormat::Uint32,
-                        );
-                        render_pass.draw_indexed(0..self.texindex_length, 0, k..k + 1);
-                        k += 1;
-                    }
-                }
+                render_pass.set_bind_group(1, &self.terrain_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass.set_vertex_buffer(1, self.chunk_index_buffer.slice(..));
+                render_pass.set_index_buffer(self.tex_index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(grid.lines.clone(), 0, 0..self.chunks);
             }
         }
-        self.fps_counter.print_fps(5);
         self.init.queue.submit(iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
     }
-}pub fn create_bind_group_layout_storage(device: &wgpu::Device, shader_stages: Vec<wgpu::ShaderStages>, binding_types: Vec<wgpu::BufferBindingType>) -> wgpu::BindGroupLayout {
+}fn create_pipelines(init: &WgpuInit, shader: &wgpu::ShaderModule, layout: &wgpu::PipelineLayout, texture_layout: &wgpu::PipelineLayout) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+    //the shaded terrain and the line pipeline drawn over it, both from the same shader
+    let chunk_index_layout = VertexBufferLayout {
+        array_stride: mem::size_of::<u32>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![2 => Uint32],
+    };
+    //And vertex buffer layout
+    let vertex_buffer_layout = VertexBufferLayout {
+        array_stride: mem::size_of::<surface::Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &wgpu::vertex_attr_array![0 => Uint32x2, 1 => Uint32], // sample within the chunk and the skirt flag, location 2 is the chunk index
+    };
+    //Initialised pipeline based on the layout
+    let mut ppl = RenderPipeline {
+        shader: Some(shader),
+        pipeline_layout: Some(layout),
+        vertex_buffer_layout: &[vertex_buffer_layout.clone(), chunk_index_layout.clone()],
+        ..Default::default()
+    };
+    let pipeline = ppl.build(init);
+    let mut pplt = RenderPipeline {
+        topology: wgpu::PrimitiveTopology::LineList,
+        vs_entry: String::from("vs_lines"),
+        fs_entry: String::from("fs_lines"),
+        shader: Some(shader),
+        pipeline_layout: Some(texture_layout),
+        vertex_buffer_layout: &[vertex_buffer_layout, chunk_index_layout],
+        ..Default::default()
+    };
+    (pipeline, pplt.build(init))
+}
+
+fn create_model_matrices(chunks: &[surface::ChunkUniform], moves: [f32; 2], frame: &coords::Frame) -> Vec<[f32; 16]> {
+    //one model matrix per chunk, heights are in metres so the y scale turns them into render units
+    //and x is squeezed because samples get closer together east to west away from the equator
+    //the fractional part of moves only goes in here, the shader reads samples from the whole chunk origin
+    let (east_west, vertical) = (frame.east_west_scale(), frame.vertical_scale);
+    chunks.iter().map(|c| {
+        let t = [(c.origin[0] as f64 - moves[0] as f64) as f32, (c.origin[1] as f64 - moves[1] as f64) as f32];
+        let scale = c.scale as f32;
+        let m = transforms::create_transforms([t[0] * east_west, coords::RENDER_BASE_HEIGHT, t[1]], [0.0, 0.0, 0.0], [east_west * scale, vertical, scale]);
+        *(m.as_ref())
+    }).collect()
+}
+
+pub fn create_bind_group_layout_storage(device: &wgpu::Device, shader_stages: Vec<wgpu::ShaderStages>, binding_types: Vec<wgpu::BufferBindingType>) -> wgpu::BindGroupLayout {
     //function to create bind group layout returns wgpu object BindGroupLayout
     let mut entries = vec![];
 
@@ -879,6 +1201,49 @@ pub fn create_bind_group_storage(device: &wgpu::Device, shader_stages: Vec<wgpu:
 }
 
 
+fn create_terrain_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    //terrain uniform, chunk offsets, colour ramp stops then the height and void textures, all read by the vertex shader
+    let buffer = |ty| wgpu::BindingType::Buffer { ty, has_dynamic_offset: false, min_binding_size: None };
+    let texture = |sample_type| wgpu::BindingType::Texture {
+        sample_type,
+        view_dimension: wgpu::TextureViewDimension::D2Array,
+        multisampled: false,
+    };
+    let types = [
+        buffer(wgpu::BufferBindingType::Uniform),
+        buffer(wgpu::BufferBindingType::Storage { read_only: true }),
+        buffer(wgpu::BufferBindingType::Storage { read_only: true }),
+        texture(wgpu::TextureSampleType::Float { filterable: false }),
+        texture(wgpu::TextureSampleType::Uint),
+    ];
+    let entries: Vec<_> = types.into_iter().enumerate().map(|(i, ty)| wgpu::BindGroupLayoutEntry {
+        binding: i as u32,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty,
+        count: None,
+    }).collect();
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &entries,
+        label: Some("Terrain Bind Group Layout"),
+    })
+}
+
+fn create_terrain_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, buffers: &[&wgpu::Buffer; 3], heightmap: &heightmap::Heightmap) -> wgpu::BindGroup {
+    let (heights, voids) = heightmap.views();
+    let mut resources: Vec<wgpu::BindingResource> = buffers.iter().map(|b| b.as_entire_binding()).collect();
+    resources.push(wgpu::BindingResource::TextureView(&heights));
+    resources.push(wgpu::BindingResource::TextureView(&voids));
+    let entries: Vec<_> = resources.into_iter().enumerate().map(|(i, resource)| wgpu::BindGroupEntry {
+        binding: i as u32,
+        resource,
+    }).collect();
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &entries,
+        label: Some("Terrain Bind Group"),
+    })
+}
+
 pub fn create_color_attachment<'a>(texture_view: &'a wgpu::TextureView) -> wgpu::RenderPassColorAttachment<'a> {
     let mut blue = wgpu::Color::BLUE;
     //light blue
@@ -927,26 +1292,26 @@ pub fn create_depth_stencil_attachment<'a>(depth_view: &'a wgpu::TextureView) ->
 }
 
 //Application starts
-pub fn run() {
+pub fn run(settings: &settings::Settings) -> Result<(), String> {
     env_logger::init();
     //initialise the environment logger
     let event_loop = EventLoop::new();
     //initialise the window that will be used to display the render
     let window = winit::window::WindowBuilder::new()
+        .with_inner_size(winit::dpi::PhysicalSize::new(settings.width, settings.height))
         .build(&event_loop)
-        .unwrap();
-    window.set_title(&*format!("Honours"));
-    // initialise the struct "state" thread blocked until state is initialised
-    let mut state = pollster::block_on(State::new(
-        &window,
-    ));
+        .map_err(|e| format!("window could not be created: {}", e))?;
+    window.set_title("Honours");
+    // initialise the struct "state" thread blocked until wgpu is initialised
+    let init = pollster::block_on(WgpuInit::new(&window, 1, None));
+    let mut state = State::new(init, settings)?;
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
             window_id,
-        } if window_id == window.id() => {
-            if !state.input(event) {
+        } if window_id == window.id()
+            && !state.input(event) => {
                 match event {
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
@@ -968,6 +1333,9 @@ pub fn run() {
                     _ => {}
                 }
             }
+        Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => {
+            //raw mouse movement keeps working when the cursor reaches the edge of the window
+            state.mouse_look(delta);
         }
         Event::RedrawRequested(_) => {
             state.update();
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::Deserialize;
+use winit::event::VirtualKeyCode;
+use super::CamPos;
+use super::color_ramp::{Blend, ColorRamp};
+use super::flight::Aircraft;
+use super::keys::Action;
+use super::settings::Settings;
+
+pub const DEFAULT_CONFIG: &str = "threedcube.toml";//read from the working directory when --config is not given
+
+//every section and value is optional, anything left out keeps its default
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct ConfigFile {
+    data: DataSection,
+    start: StartSection,
+    chunks: ChunkSection,
+    terrain: TerrainSection,
+    colours: ColourSection,
+    lod: LodSection,
+    view: ViewSection,
+    lighting: LightingSection,
+    taws: TawsSection,
+    flight: FlightSection,
+    dev: DevSection,
+    keys: HashMap<Action, VirtualKeyCode>,//action = "Key" like forward = "W"
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct DataSection {
+    dir: Option<PathBuf>,//relative to the config file
+    source: Option<String>,
+    void_fill: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct StartSection {
+    position: Option<String>,//"latitude,longitude"
+    camera: Option<[f32; 3]>,
+    look: Option<[f32; 3]>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct ChunkSection {
+    size: Option<u32>,//vertices along each side of a chunk
+    max: Option<u32>,//most quadtree chunks drawn at once
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct TerrainSection {
+    water_level: Option<f32>,
+    vertical_exaggeration: Option<f32>,
+    interpolation: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct ColourSection {
+    height_max: Option<f32>,//metres at the top of the last band
+    thresholds: Option<Vec<f32>>,//band edges from 0 to 1 of height_max, one more than there are bands
+    bands: Option<Vec<[f32; 3]>>,//rgb from 0 to 1, the first band is water
+    mode: Option<String>,//elevation, hillshade, slope, aspect, tinted or taws
+    ramp: Option<String>,//colour ramp to start with
+    ramps: Vec<RampFile>,//[[colours.ramps]] tables
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RampFile {
+    file: PathBuf,//gdal colour relief text, relative to the config file
+    blend: Option<String>,//smooth or stepped
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct LodSection {
+    initial: Option<u32>,
+    steps: Option<Vec<u32>>,//samples skipped between vertices at each level of detail
+    pixel_error: Option<f32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct ViewSection {
+    fov: Option<f32>,//vertical field of view in degrees
+    near: Option<f32>,//clipping planes in render units
+    far: Option<f32>,
+    distance: Option<f32>,//km the terrain is drawn out to
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct LightingSection {
+    azimuth: Option<f32>,//degrees clockwise from north
+    elevation: Option<f32>,//degrees above the horizon
+    ambient: Option<f32>,//0 to 1
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct TawsSection {
+    caution: Option<f32>,//feet below the camera
+    warning: Option<f32>,
+    look_ahead: Option<f32>,//seconds of flight path checked for terrain
+    caution_time: Option<f32>,//seconds to impact
+    pull_up_time: Option<f32>,
+    path_clearance: Option<f32>,//metres the projected flight path keeps above the terrain
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct FlightSection {
+    speed: Option<f32>,//metres per second along the heading
+    mouse_sensitivity: Option<f32>,//degrees per pixel
+    aircraft: Option<String>,//built in name or a .toml file relative to the config file
+    fly: Option<bool>,//false starts with the free camera
+    min_clearance: Option<f32>,//metres above the ground or water
+    floor: Option<bool>,//false lets the camera through the ground, the warnings still come
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct DevSection {
+    shader: Option<PathBuf>,//wgsl reloaded whenever it changes, relative to the config file
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_toml(&text, path.parent().unwrap_or(Path::new(""))).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn from_toml(text: &str, base: &Path) -> Result<Self, String> {
+        //paths in the file are relative to base, the file's directory rather than the working directory
+        let mut config: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        let ramps = config.colours.ramps.iter_mut().map(|r| &mut r.file);
+        for file in [&mut config.data.dir, &mut config.dev.shader].into_iter().flatten().chain(ramps) {
+            if file.is_relative() {
+                *file = base.join(&*file);
+            }
+        }
+        if let Some(aircraft) = config.flight.aircraft.as_mut().filter(|a| a.ends_with(".toml") && Path::new(a.as_str()).is_relative()) {
+            *aircraft = base.join(&*aircraft).to_string_lossy().into_owned();
+        }
+        Ok(config)
+    }
+
+    pub fn apply(self, settings: &mut Settings) -> Result<(), String> {
+        //copy every value the file sets over the defaults, the command line is applied after this
+        set(&mut settings.data_dir, self.data.dir);
+        set(&mut settings.source, parse("data.source", self.data.source)?);
+        set(&mut settings.void_fill, parse("data.void_fill", self.data.void_fill)?);
+        set(&mut settings.start, parse("start.position", self.start.position)?);
+        set(&mut settings.camera, self.start.camera.map(|[x, y, z]| CamPos { x, y, z }));
+        set(&mut settings.camlook, self.start.look.map(|[x, y, z]| CamPos { x, y, z }));
+        set(&mut settings.max_chunks, self.chunks.max);
+        set(&mut settings.view_distance, self.view.distance);
+        set(&mut settings.chunksize, self.chunks.size);
+        set(&mut settings.water_level, self.terrain.water_level);
+        set(&mut settings.vertical_exaggeration, self.terrain.vertical_exaggeration);
+        set(&mut settings.interpolation, parse("terrain.interpolation", self.terrain.interpolation)?);
+        set(&mut settings.colour_height_max, self.colours.height_max);
+        set(&mut settings.colour_thresholds, self.colours.thresholds);
+        set(&mut settings.colours, self.colours.bands);
+        set(&mut settings.colour_mode, parse("colours.mode", self.colours.mode)?);
+        set(&mut settings.ramp, self.colours.ramp);
+        for ramp in self.colours.ramps {
+            let blend = parse("colours.ramps.blend", ramp.blend)?.unwrap_or(Blend::Smooth);
+            settings.ramp_files.push(ColorRamp::load_gdal(&ramp.file, blend)?);
+        }
+        set(&mut settings.level_of_detail, self.lod.initial);
+        set(&mut settings.lod_steps, self.lod.steps);
+        set(&mut settings.pixel_error, self.lod.pixel_error);
+        set(&mut settings.fov, self.view.fov);
+        set(&mut settings.near, self.view.near);
+        set(&mut settings.far, self.view.far);
+        set(&mut settings.width, self.view.width);
+        set(&mut settings.height, self.view.height);
+        set(&mut settings.sun.azimuth, self.lighting.azimuth);
+        set(&mut settings.sun.elevation, self.lighting.elevation);
+        set(&mut settings.sun.ambient, self.lighting.ambient);
+        set(&mut settings.taws_caution, self.taws.caution);
+        set(&mut settings.taws_warning, self.taws.warning);
+        set(&mut settings.taws_alerts.look_ahead, self.taws.look_ahead);
+        set(&mut settings.taws_alerts.caution, self.taws.caution_time);
+        set(&mut settings.taws_alerts.pull_up, self.taws.pull_up_time);
+        set(&mut settings.taws_alerts.clearance, self.taws.path_clearance);
+        set(&mut settings.speed, self.flight.speed);
+        set(&mut settings.mouse_sensitivity, self.flight.mouse_sensitivity);
+        let aircraft = self.flight.aircraft.map(|a| Aircraft::from_name_or_file(&a)).transpose();
+        set(&mut settings.aircraft, aircraft.map_err(|e| format!("flight.aircraft: {}", e))?);
+        set(&mut settings.fly, self.flight.fly);
+        set(&mut settings.min_clearance, self.flight.min_clearance);
+        set(&mut settings.floor, self.flight.floor);
+        set(&mut settings.shader, self.dev.shader.map(Some));
+        settings.keys.rebind(&self.keys).map_err(|e| format!("keys: {}", e))
+    }
+}
+
+fn set<T>(setting: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *setting = value;
+    }
+}
+
+fn parse<T: FromStr<Err = String>>(name: &str, value: Option<String>) -> Result<Option<T>, String> {
+    value.map(|v| v.parse().map_err(|e| format!("{}: {}", name, e))).transpose()
+}
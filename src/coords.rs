@@ -0,0 +1,86 @@
+use std::str::FromStr;
+
+pub const METRES_PER_DEGREE: f64 = 111_320.0;//north south length of one degree of latitude
+pub const RENDER_BASE_HEIGHT: f32 = 10.0;//render y of sea level, the model matrix translation of every chunk
+pub const START_POSITION_ENV: &str = "START_POSITION";//environment variable placing the camera at a latitude,longitude
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GeoPoint {//wgs84 latitude and longitude in degrees, south and west are negative
+    pub lat: f64,
+    pub long: f64,
+}
+
+impl FromStr for GeoPoint {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        //"55.8719,-4.4331" for glasgow airport
+        let (lat, long) = s.split_once(',').ok_or_else(|| format!("expected latitude,longitude but got '{}'", s))?;
+        let lat: f64 = lat.trim().parse().map_err(|_| format!("latitude '{}' is not a number", lat.trim()))?;
+        let long: f64 = long.trim().parse().map_err(|_| format!("longitude '{}' is not a number", long.trim()))?;
+        if !(-90.0..=90.0).contains(&lat) {
+            return Err(format!("latitude {} is outside -90 to 90", lat));
+        }
+        if !(-180.0..=180.0).contains(&long) {
+            return Err(format!("longitude {} is outside -180 to 180", long));
+        }
+        Ok(GeoPoint { lat, long })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Frame {//links render space, sample indices and wgs84 latitude and longitude for the current terrain position
+    pub lat: i32,//south west corner of the tile sample indices count from
+    pub long: i32,
+    pub moves: [f32; 2],//full resolution sample at the render origin, counted east and south from the north west corner of the tile
+    pub samples_per_degree: u32,//full resolution
+    pub resolution: u32,//samples per degree being rendered, one render unit north to south is one of these samples
+    pub vertical_scale: f32,//render units per metre of height
+}
+
+impl Frame {
+    pub fn origin(&self) -> (f64, f64) {
+        //latitude and longitude at the render origin
+        self.sample_to_geo(self.moves[0] as f64, self.moves[1] as f64)
+    }
+
+    pub fn east_west_scale(&self) -> f32 {
+        //render x is squeezed so a render unit is the same distance on the ground both ways
+        self.origin().0.to_radians().cos() as f32
+    }
+
+    pub fn metres_per_sample(&self) -> f64 {
+        //north to south at the resolution being rendered, one render unit
+        METRES_PER_DEGREE / self.resolution as f64
+    }
+
+    pub fn sample_to_geo(&self, sx: f64, sz: f64) -> (f64, f64) {
+        let spd = self.samples_per_degree as f64;
+        (self.lat as f64 + 1.0 - sz / spd, self.long as f64 + sx / spd)
+    }
+
+    pub fn geo_to_sample(&self, lat: f64, long: f64) -> (f64, f64) {
+        let spd = self.samples_per_degree as f64;
+        ((long - self.long as f64) * spd, (self.lat as f64 + 1.0 - lat) * spd)
+    }
+
+    pub fn render_to_sample(&self, x: f32, z: f32) -> (f64, f64) {
+        let per_sample = self.samples_per_degree as f64 / self.resolution as f64;
+        (
+            self.moves[0] as f64 + x as f64 / self.east_west_scale() as f64 * per_sample,
+            self.moves[1] as f64 + z as f64 * per_sample,
+        )
+    }
+
+    pub fn render_to_geo(&self, x: f32, z: f32) -> (f64, f64) {
+        let (sx, sz) = self.render_to_sample(x, z);
+        self.sample_to_geo(sx, sz)
+    }
+
+    pub fn render_y_to_metres(&self, y: f32) -> f32 {
+        (y - RENDER_BASE_HEIGHT) / self.vertical_scale
+    }
+
+    pub fn metres_to_render_y(&self, metres: f32) -> f32 {
+        RENDER_BASE_HEIGHT + metres * self.vertical_scale
+    }
+}
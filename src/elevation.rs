@@ -0,0 +1,71 @@
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use super::hgt::{self, Hgt};
+use super::tiles::TileResolver;
+use super::void_fill::SRTM_VOID;
+use super::geotiff::GeoTiffSource;
+
+pub const SOURCE_ENV: &str = "ELEVATION_SOURCE";//environment variable choosing hgt or geotiff
+
+pub struct RawTile {//one degree tile as the source has it, extent samples per side from the north west corner
+    pub extent: u32,
+    pub heights: Vec<f32>,
+    pub voids: Vec<bool>,//true where the source has no data
+}
+
+pub trait ElevationSource: Send + Sync {//somewhere heights can be read from one degree tile at a time
+    //the tile whose south west corner is lat long, None when the source has nothing there
+    fn load(&self, lat: i32, long: i32) -> io::Result<Option<RawTile>>;
+    //native resolution of the tile without reading all of it
+    fn samples_per_degree(&self, lat: i32, long: i32) -> Option<u32>;
+}
+
+pub struct HgtSource {//srtm hgt files named by their south west corner
+    pub resolver: TileResolver,
+}
+
+impl ElevationSource for HgtSource {
+    fn load(&self, lat: i32, long: i32) -> io::Result<Option<RawTile>> {
+        let path = self.resolver.path(lat, long);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let hgt = Hgt::from_file(&path)?;
+        Ok(Some(RawTile {
+            extent: hgt.extent,
+            voids: hgt.data.iter().map(|y| *y == SRTM_VOID).collect(),
+            heights: hgt.data.iter().map(|y| *y as f32).collect(),
+        }))
+    }
+
+    fn samples_per_degree(&self, lat: i32, long: i32) -> Option<u32> {
+        hgt::samples_per_degree(self.resolver.path(lat, long))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum SourceKind {//elevation formats that can be chosen at startup
+    #[default]
+    Hgt,
+    GeoTiff,
+}
+
+impl FromStr for SourceKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hgt" | "srtm" => Ok(SourceKind::Hgt),
+            "geotiff" | "tiff" | "tif" => Ok(SourceKind::GeoTiff),
+            _ => Err(format!("unknown elevation source '{}', expected hgt or geotiff", s)),
+        }
+    }
+}
+
+pub fn open_source(kind: SourceKind, root: PathBuf) -> Result<Arc<dyn ElevationSource>, String> {
+    match kind {
+        SourceKind::Hgt => Ok(Arc::new(HgtSource { resolver: TileResolver::new(root) })),
+        SourceKind::GeoTiff => Ok(Arc::new(GeoTiffSource::open(&root)?)),
+    }
+}
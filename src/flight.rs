@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+
+pub const FIXED_STEP: f32 = 1.0 / 60.0;//seconds per integration step whatever the frame rate
+const GRAVITY: f32 = 9.81;
+pub const THROTTLE_PER_SECOND: f32 = 0.5;//while a throttle key is held
+pub const BANK_PER_SECOND: f32 = 30.0;//degrees the bank asked for moves while a roll key is held
+pub const PITCH_PER_SECOND: f32 = 10.0;
+pub const YAW_PER_SECOND: f32 = 5.0;
+const MAX_PITCH: f32 = 30.0;//degrees of flight path the controls ask for at most
+const STALL_NOSE_DOWN: f32 = -15.0;//degrees the nose drops to once stalled
+const STALL_RECOVERY: f32 = 1.1;//times the stall speed before the wing flies again
+pub const BUILTIN_AIRCRAFT: [&str; 2] = ["c172", "pa28"];
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Aircraft {//performance of one aircraft type, speeds in metres per second and angles in degrees
+    pub name: String,
+    pub stall_speed: f32,
+    pub max_speed: f32,//level flight at full throttle, drag balances thrust here
+    pub acceleration: f32,//metres per second squared at full throttle before drag
+    pub max_bank: f32,
+    pub roll_rate: f32,//degrees per second
+    pub pitch_rate: f32,
+}
+
+impl Aircraft {
+    pub fn builtin(name: &str) -> Option<Self> {
+        let (stall_speed, max_speed, acceleration, max_bank, roll_rate, pitch_rate) = match name {
+            "c172" => (24.0, 63.0, 2.5, 60.0, 30.0, 8.0),
+            "pa28" => (25.0, 66.0, 2.6, 60.0, 30.0, 8.0),
+            _ => return None,
+        };
+        Some(Self { name: name.to_string(), stall_speed, max_speed, acceleration, max_bank, roll_rate, pitch_rate })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        //toml with a value for every field, like name = "c172" stall_speed = 24.0 ...
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let aircraft: Self = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        aircraft.validate().map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(aircraft)
+    }
+
+    pub fn from_name_or_file(value: &str) -> Result<Self, String> {
+        //a built in aircraft or a .toml file of one
+        if value.ends_with(".toml") {
+            return Self::load(Path::new(value));
+        }
+        Self::builtin(value).ok_or_else(|| format!("unknown aircraft '{}', expected one of {} or a .toml file", value, BUILTIN_AIRCRAFT.join(", ")))
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let values = [self.stall_speed, self.max_speed, self.acceleration, self.max_bank, self.roll_rate, self.pitch_rate];
+        if !values.iter().all(|v| *v > 0.0 && v.is_finite()) {
+            return Err(format!("aircraft {} needs every speed, rate and angle more than 0", self.name));
+        }
+        if self.stall_speed >= self.max_speed {
+            return Err(format!("aircraft {} stalls at {} m/s, above its top speed of {}", self.name, self.stall_speed, self.max_speed));
+        }
+        if self.max_bank >= 90.0 {
+            return Err(format!("aircraft {} can bank at most 89 degrees, got {}", self.name, self.max_bank));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StallEvent {//raised as the wing stops flying and again once it flies
+    Stalled,
+    Recovered,
+}
+
+#[derive(Clone, Debug)]
+pub struct FlightModel {//point mass flown with a fixed timestep, the camera follows its attitude
+    aircraft: Aircraft,
+    pub throttle: f32,//0 to 1
+    pub airspeed: f32,//metres per second
+    pub heading: f32,//degrees clockwise from north
+    pub flight_path: f32,//degrees above the horizon the aircraft is moving
+    pub bank: f32,//degrees, right wing down is positive
+    target_pitch: f32,//flight path the controls ask for
+    target_bank: f32,
+    pub stalled: bool,
+    unflown: f32,//seconds of frame time not covered by a whole step yet
+}
+
+impl FlightModel {
+    pub fn new(aircraft: Aircraft, heading: f32, flight_path: f32, airspeed: f32) -> Self {
+        //starts in balanced level flight at the airspeed, with the throttle that holds it
+        let flight_path = flight_path.clamp(-MAX_PITCH, MAX_PITCH);
+        let airspeed = airspeed.min(aircraft.max_speed);
+        let throttle = (airspeed / aircraft.max_speed).powi(2);
+        Self {
+            aircraft,
+            throttle,
+            airspeed,
+            heading,
+            flight_path,
+            bank: 0.0,
+            target_pitch: flight_path,
+            target_bank: 0.0,
+            stalled: false,
+            unflown: 0.0,
+        }
+    }
+
+    pub fn aircraft(&self) -> &Aircraft {
+        &self.aircraft
+    }
+
+    pub fn climb_rate(&self) -> f32 {
+        //metres per second
+        self.airspeed * self.flight_path.to_radians().sin()
+    }
+
+    pub fn ground_speed(&self) -> f32 {
+        //metres per second over the ground, there is no wind
+        self.airspeed * self.flight_path.to_radians().cos()
+    }
+
+    pub fn climb_gradient(&self) -> f32 {
+        //height per metre flown the aircraft could hold at full throttle and this airspeed
+        let aircraft = &self.aircraft;
+        let drag = aircraft.acceleration / (aircraft.max_speed * aircraft.max_speed);
+        let sin = ((aircraft.acceleration - drag * self.airspeed * self.airspeed) / GRAVITY).clamp(0.0, 1.0);
+        if self.stalled { 0.0 } else { sin / (1.0 - sin * sin).sqrt().max(f32::EPSILON) }
+    }
+
+    pub fn change_throttle(&mut self, delta: f32) {
+        self.throttle = (self.throttle + delta).clamp(0.0, 1.0);
+    }
+
+    pub fn change_pitch(&mut self, delta: f32) {
+        self.target_pitch = (self.target_pitch + delta).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    pub fn change_bank(&mut self, delta: f32) {
+        self.target_bank = (self.target_bank + delta).clamp(-self.aircraft.max_bank, self.aircraft.max_bank);
+    }
+
+    pub fn yaw(&mut self, delta: f32) {
+        //a touch of rudder, straight onto the heading
+        self.heading = (self.heading + delta).rem_euclid(360.0);
+    }
+
+    pub fn touch_down(&mut self) {
+        //held up by the ground, the aircraft skims along it rather than sinking into it
+        self.flight_path = self.flight_path.max(0.0);
+    }
+
+    pub fn level(&mut self) {
+        self.target_pitch = 0.0;
+        self.target_bank = 0.0;
+    }
+
+    pub fn advance(&mut self, seconds: f32) -> ([f32; 3], Option<StallEvent>) {
+        //runs as many fixed steps as the frame time covers, returns metres moved east, up and north
+        //and an event when the frame ends stalled and it began flying, or the other way round
+        let was_stalled = self.stalled;
+        self.unflown += seconds;
+        let mut moved = [0.0; 3];
+        while self.unflown >= FIXED_STEP {
+            let step = self.step(FIXED_STEP);
+            for (total, m) in moved.iter_mut().zip(step) {
+                *total += m;
+            }
+            self.unflown -= FIXED_STEP;
+        }
+        let event = match (was_stalled, self.stalled) {
+            (false, true) => Some(StallEvent::Stalled),
+            (true, false) => Some(StallEvent::Recovered),
+            _ => None,
+        };
+        (moved, event)
+    }
+
+    fn step(&mut self, dt: f32) -> [f32; 3] {
+        let aircraft = &self.aircraft;
+        //the wing stops flying below the stall speed and the nose drops until there is speed again
+        if !self.stalled && self.airspeed < aircraft.stall_speed {
+            self.stalled = true;
+        } else if self.stalled && self.airspeed > aircraft.stall_speed * STALL_RECOVERY {
+            self.stalled = false;
+        }
+        let target_pitch = if self.stalled { STALL_NOSE_DOWN } else { self.target_pitch };
+        self.flight_path += (target_pitch - self.flight_path).clamp(-aircraft.pitch_rate * dt, aircraft.pitch_rate * dt);
+        self.bank += (self.target_bank - self.bank).clamp(-aircraft.roll_rate * dt, aircraft.roll_rate * dt);
+        //thrust less drag that grows with the square of the airspeed, climbing trades speed for height
+        let gamma = self.flight_path.to_radians();
+        let drag = aircraft.acceleration / (aircraft.max_speed * aircraft.max_speed);
+        let acceleration = self.throttle * aircraft.acceleration - drag * self.airspeed * self.airspeed - GRAVITY * gamma.sin();
+        self.airspeed = (self.airspeed + acceleration * dt).max(0.0);
+        //a banked wing turns the aircraft, faster the slower it flies
+        let turn_rate = (GRAVITY * self.bank.to_radians().tan() / self.airspeed.max(1.0)).to_degrees();
+        self.heading = (self.heading + turn_rate * dt).rem_euclid(360.0);
+        let heading = self.heading.to_radians();
+        let (along, up) = (self.airspeed * gamma.cos() * dt, self.airspeed * gamma.sin() * dt);
+        [along * heading.sin(), up, along * heading.cos()]
+    }
+}
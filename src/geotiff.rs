@@ -0,0 +1,238 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+use super::elevation::{ElevationSource, RawTile};
+
+//geokeys needed to check the raster is in wgs84 latitude and longitude
+const GT_MODEL_TYPE_GEOKEY: u16 = 1024;
+const GT_RASTER_TYPE_GEOKEY: u16 = 1025;
+const GEOGRAPHIC_TYPE_GEOKEY: u16 = 2048;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+const GCS_WGS_84: u16 = 4326;
+
+struct Raster {//one geotiff file and where its pixels are
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    x0: f64,//longitude of the centre of the first pixel
+    y0: f64,//latitude of the centre of the first pixel
+    scale_x: f64,//degrees per pixel
+    scale_y: f64,
+    nodata: Option<f64>,
+}
+
+impl Raster {
+    fn open(path: &Path) -> Result<Self, String> {
+        let err = |e: tiff::TiffError| format!("{}: {}", path.display(), e);
+        let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut decoder = Decoder::new(BufReader::new(file)).map_err(err)?;
+        let (width, height) = decoder.dimensions().map_err(err)?;
+        let scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag).map_err(err)?;
+        let tiepoint = decoder.get_tag_f64_vec(Tag::ModelTiepointTag).map_err(err)?;
+        if scale.len() < 2 || tiepoint.len() < 6 {
+            return Err(format!("{}: incomplete georeferencing", path.display()));
+        }
+        let keys = decoder.get_tag_u16_vec(Tag::GeoKeyDirectoryTag).map(|v| geokeys(&v)).unwrap_or_default();
+        let key = |id: u16| keys.iter().find(|(k, _)| *k == id).map(|(_, v)| *v);
+        //a projected model with a wgs84 datum key is still in metres, the datum alone only counts without a model type
+        let geographic = match key(GT_MODEL_TYPE_GEOKEY) {
+            Some(model) => model == MODEL_TYPE_GEOGRAPHIC,
+            None => key(GEOGRAPHIC_TYPE_GEOKEY) == Some(GCS_WGS_84),
+        };
+        if !geographic {
+            return Err(format!("{}: only wgs84 latitude and longitude geotiffs are supported", path.display()));
+        }
+        if let Some(gcs) = key(GEOGRAPHIC_TYPE_GEOKEY) {
+            if gcs != GCS_WGS_84 {
+                return Err(format!("{}: geographic type {} is not wgs84", path.display(), gcs));
+            }
+        }
+        let (scale_x, scale_y) = (scale[0], scale[1]);
+        //the tiepoint ties raster position i j to longitude x and latitude y
+        let mut x0 = tiepoint[3] - tiepoint[0] * scale_x;
+        let mut y0 = tiepoint[4] + tiepoint[1] * scale_y;
+        if key(GT_RASTER_TYPE_GEOKEY) != Some(RASTER_PIXEL_IS_POINT) {//pixel is area so the tiepoint is the corner of the pixel
+            x0 += scale_x / 2.0;
+            y0 -= scale_y / 2.0;
+        }
+        let nodata = decoder.get_tag_ascii_string(Tag::GdalNodata).ok()
+            .and_then(|s| s.trim_matches(char::from(0)).trim().parse().ok());
+        Ok(Self { path: path.to_path_buf(), width, height, x0, y0, scale_x, scale_y, nodata })
+    }
+
+    fn covers(&self, lat: i32, long: i32) -> bool {
+        //true if the raster touches the one degree tile, edges included
+        let east = self.x0 + (self.width - 1) as f64 * self.scale_x;
+        let south = self.y0 - (self.height - 1) as f64 * self.scale_y;
+        east >= long as f64 && self.x0 <= long as f64 + 1.0 && self.y0 >= lat as f64 && south <= lat as f64 + 1.0
+    }
+
+    fn samples_per_degree(&self) -> u32 {
+        //the finer axis, copernicus has wider longitude spacing away from the equator
+        (1.0 / self.scale_x.min(self.scale_y)).round().max(1.0) as u32
+    }
+
+    fn window(&self, lat: i32, long: i32) -> (Range<u32>, Range<u32>) {
+        //columns and rows of the pixels around the one degree tile, one extra each side for the bilinear neighbours
+        let col = |long: f64| ((long - self.x0) / self.scale_x).clamp(0.0, (self.width - 1) as f64);
+        let row = |lat: f64| ((self.y0 - lat) / self.scale_y).clamp(0.0, (self.height - 1) as f64);
+        let cols = (col(long as f64).floor() as u32).saturating_sub(1)..(col(long as f64 + 1.0).ceil() as u32 + 2).min(self.width);
+        let rows = (row(lat as f64 + 1.0).floor() as u32).saturating_sub(1)..(row(lat as f64).ceil() as u32 + 2).min(self.height);
+        (cols, rows)
+    }
+
+    fn read(&self, cols: Range<u32>, rows: Range<u32>) -> io::Result<Window> {
+        //decodes only the strips or tiles overlapping the window, a national dem is far too big to decode for every tile
+        let to_io = |e: tiff::TiffError| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", self.path.display(), e));
+        let mut decoder = Decoder::new(BufReader::new(File::open(&self.path)?)).map_err(to_io)?;
+        let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+        let across = self.width.div_ceil(chunk_width);//1 for strips, which are the full width
+        let width = cols.end - cols.start;
+        let mut data = vec![f32::NAN; (width * (rows.end - rows.start)) as usize];
+        for chunk_row in rows.start / chunk_height..rows.end.div_ceil(chunk_height) {
+            for chunk_col in cols.start / chunk_width..cols.end.div_ceil(chunk_width) {
+                let index = chunk_row * across + chunk_col;
+                let (data_width, data_height) = decoder.chunk_data_dimensions(index);
+                let chunk = to_f32(decoder.read_chunk(index).map_err(to_io)?)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}: only float32 and int16 samples are supported", self.path.display())))?;
+                let (left, top) = (chunk_col * chunk_width, chunk_row * chunk_height);
+                for r in top.max(rows.start)..(top + data_height).min(rows.end) {
+                    let c = left.max(cols.start)..(left + data_width).min(cols.end);
+                    let from = ((r - top) * data_width + c.start - left) as usize;
+                    let to = ((r - rows.start) * width + c.start - cols.start) as usize;
+                    let len = (c.end - c.start) as usize;
+                    data[to..to + len].copy_from_slice(&chunk[from..from + len]);
+                }
+            }
+        }
+        Ok(Window { cols, rows, data })
+    }
+
+    fn sample(&self, window: &Window, lat: f64, long: f64) -> Option<f32> {
+        //bilinear height at a latitude and longitude, None outside the raster or next to no data
+        let col = (long - self.x0) / self.scale_x;
+        let row = (self.y0 - lat) / self.scale_y;
+        let max_col = (self.width - 1) as f64;
+        let max_row = (self.height - 1) as f64;
+        const EDGE: f64 = 1e-6;//rounding at the raster edge still counts as inside
+        if col < -EDGE || row < -EDGE || col > max_col + EDGE || row > max_row + EDGE {
+            return None;
+        }
+        let col = col.clamp(0.0, max_col);
+        let row = row.clamp(0.0, max_row);
+        let c0 = (col.floor() as u32).min(self.width.saturating_sub(2));
+        let r0 = (row.floor() as u32).min(self.height.saturating_sub(2));
+        let c1 = (c0 + 1).min(self.width - 1);
+        let r1 = (r0 + 1).min(self.height - 1);
+        let tx = (col - c0 as f64) as f32;
+        let tz = (row - r0 as f64) as f32;
+        let get = |c: u32, r: u32, weight: f32| {
+            if weight == 0.0 {//samples exactly on a pixel do not need its neighbours
+                return Some(0.0);
+            }
+            let v = window.get(c, r)?;
+            match self.nodata {
+                Some(nodata) if (v as f64 - nodata).abs() < 1e-3 => None,
+                _ if v.is_nan() => None,
+                _ => Some(v * weight),
+            }
+        };
+        Some(get(c0, r0, (1.0 - tx) * (1.0 - tz))? + get(c1, r0, tx * (1.0 - tz))?
+            + get(c0, r1, (1.0 - tx) * tz)? + get(c1, r1, tx * tz)?)
+    }
+}
+
+struct Window {//pixels decoded from part of a raster
+    cols: Range<u32>,
+    rows: Range<u32>,
+    data: Vec<f32>,
+}
+
+impl Window {
+    fn get(&self, col: u32, row: u32) -> Option<f32> {
+        //None outside the window, which only happens for tiles the window was not read for
+        if !self.cols.contains(&col) || !self.rows.contains(&row) {
+            return None;
+        }
+        Some(self.data[((row - self.rows.start) * (self.cols.end - self.cols.start) + col - self.cols.start) as usize])
+    }
+}
+
+fn to_f32(result: DecodingResult) -> Option<Vec<f32>> {
+    match result {
+        DecodingResult::F32(data) => Some(data),
+        DecodingResult::I16(data) => Some(data.into_iter().map(|v| v as f32).collect()),
+        DecodingResult::U16(data) => Some(data.into_iter().map(|v| v as f32).collect()),
+        DecodingResult::F64(data) => Some(data.into_iter().map(|v| v as f32).collect()),
+        _ => None,
+    }
+}
+
+pub struct GeoTiffSource {//a directory of wgs84 geotiff dems such as copernicus glo-30
+    rasters: Vec<Raster>,
+}
+
+impl GeoTiffSource {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let mut rasters = vec![];
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let ext = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+            if !matches!(ext.as_deref(), Some("tif") | Some("tiff")) {
+                continue;
+            }
+            match Raster::open(&path) {
+                Ok(raster) => rasters.push(raster),
+                Err(e) => eprintln!("skipping geotiff {}", e),
+            }
+        }
+        if rasters.is_empty() {
+            return Err(format!("no usable geotiff files in {}", dir.display()));
+        }
+        Ok(Self { rasters })
+    }
+}
+
+impl ElevationSource for GeoTiffSource {
+    fn load(&self, lat: i32, long: i32) -> io::Result<Option<RawTile>> {
+        let covering: Vec<&Raster> = self.rasters.iter().filter(|r| r.covers(lat, long)).collect();
+        let Some(samples) = covering.iter().map(|r| r.samples_per_degree()).max() else {
+            return Ok(None);
+        };
+        let data = covering.iter().map(|r| {
+            let (cols, rows) = r.window(lat, long);
+            r.read(cols, rows)
+        }).collect::<io::Result<Vec<_>>>()?;
+        let extent = samples + 1;
+        let mut heights = Vec::with_capacity((extent * extent) as usize);
+        let mut voids = Vec::with_capacity((extent * extent) as usize);
+        for z in 0..extent {
+            let sample_lat = lat as f64 + 1.0 - z as f64 / samples as f64;
+            for x in 0..extent {
+                let sample_long = long as f64 + x as f64 / samples as f64;
+                //the first raster with data at the point wins where rasters overlap
+                let height = covering.iter().zip(&data).find_map(|(r, d)| r.sample(d, sample_lat, sample_long));
+                heights.push(height.unwrap_or(0.0));
+                voids.push(height.is_none());
+            }
+        }
+        Ok(Some(RawTile { extent, heights, voids }))
+    }
+
+    fn samples_per_degree(&self, lat: i32, long: i32) -> Option<u32> {
+        self.rasters.iter().filter(|r| r.covers(lat, long)).map(|r| r.samples_per_degree()).max()
+    }
+}
+
+fn geokeys(directory: &[u16]) -> Vec<(u16, u16)> {
+    //short values stored directly in the directory, keys pointing at other tags are not needed here
+    directory.get(4..).unwrap_or(&[]).chunks_exact(4)
+        .filter(|entry| entry[1] == 0)
+        .map(|entry| (entry[0], entry[3]))
+        .collect()
+}
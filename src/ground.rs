@@ -0,0 +1,47 @@
+const CLEAR_MARGIN: f32 = 10.0;//metres above the minimum clearance before another warning can be raised
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GroundEvent {//raised once as the camera gets too close, not every frame it stays there
+    LowClearance { clearance: f32 },//metres, closer than the minimum clearance
+    Collision { depth: f32 },//metres below the ground the camera tried to go
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Warned {
+    Clear,
+    Low,
+    Collided,
+}
+
+#[derive(Clone, Debug)]
+pub struct GroundGuard {//keeps the camera off the terrain and says when it gets close
+    pub min_clearance: f32,//metres above the ground
+    pub floor: bool,//hold the camera at the minimum clearance rather than let it through the ground
+    warned: Warned,
+}
+
+impl GroundGuard {
+    pub fn new(min_clearance: f32, floor: bool) -> Self {
+        Self { min_clearance, floor, warned: Warned::Clear }
+    }
+
+    pub fn check(&mut self, altitude: f32, ground: f32) -> (f32, Option<GroundEvent>) {
+        //the altitude the camera is allowed in metres, and an event the first time it gets too low or hits the ground
+        let clearance = altitude - ground;
+        let warned = if clearance < 0.0 {
+            Warned::Collided
+        } else if clearance < self.min_clearance || (clearance < self.min_clearance + CLEAR_MARGIN && self.warned != Warned::Clear) {
+            Warned::Low//skimming along at the floor should not warn over and over
+        } else {
+            Warned::Clear
+        };
+        let event = match warned {
+            Warned::Collided if self.warned != Warned::Collided => Some(GroundEvent::Collision { depth: -clearance }),
+            Warned::Low if self.warned == Warned::Clear => Some(GroundEvent::LowClearance { clearance }),
+            _ => None,
+        };
+        self.warned = warned;
+        let allowed = if self.floor { altitude.max(ground + self.min_clearance) } else { altitude };
+        (allowed, event)
+    }
+}
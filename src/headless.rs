@@ -0,0 +1,157 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use super::{State, WgpuInit};
+use super::settings::Settings;
+
+const LOAD_TIMEOUT: Duration = Duration::from_secs(300);//a tile stuck loading fails the run rather than hanging it
+
+pub fn render_png(path: &Path, settings: &Settings) -> Result<(), String> {
+    //render one frame offscreen once every tile in view has loaded and save it as a png
+    let init = pollster::block_on(WgpuInit::new_offscreen(settings.width, settings.height, 1, settings.force_fallback))?;
+    let mut state = State::new(init, settings)?;
+    state.flight = None;//the image is taken from the start, not wherever the aircraft got to while tiles loaded
+    state.update();
+    let deadline = Instant::now() + LOAD_TIMEOUT;
+    while state.terrain.tiles_loading() {//a regression image must not catch tiles halfway through loading
+        if Instant::now() > deadline {
+            return Err(format!("tiles still loading after {} seconds, no image written", LOAD_TIMEOUT.as_secs()));
+        }
+        thread::sleep(Duration::from_millis(10));
+        state.update();
+    }
+    let texture = state.init.offscreen.take().ok_or("offscreen texture missing")?;
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    state.draw(&view);
+    let pixels = read_texture(&state.init, &texture)?;
+    write_png(path, settings.width, settings.height, &pixels)
+}
+
+fn read_texture(init: &WgpuInit, texture: &wgpu::Texture) -> Result<Vec<u8>, String> {
+    //copy an rgba8 texture back to the cpu, rows are padded to the alignment wgpu needs for copies
+    let (width, height) = (init.config.width, init.config.height);
+    let unpadded = width * 4;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded = unpadded.div_ceil(align) * align;
+    let buffer = init.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size: (padded * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = init.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Readback Encoder") });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(padded), rows_per_image: Some(height) },
+        },
+        wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+    );
+    init.queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (tx, rx) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+    init.device.poll(wgpu::Maintain::Wait);
+    rx.recv().map_err(|e| e.to_string())?.map_err(|e| format!("reading the frame back failed: {}", e))?;
+    let data = slice.get_mapped_range();
+    let mut pixels = Vec::with_capacity((unpadded * height) as usize);
+    for row in data.chunks(padded as usize) {
+        pixels.extend_from_slice(&row[..unpadded as usize]);
+    }
+    drop(data);
+    buffer.unmap();
+    Ok(pixels)
+}
+
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    let err = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    let file = File::create(path).map_err(err)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| format!("{}: {}", path.display(), e))?;
+    writer.write_image_data(pixels).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use super::super::elevation::SourceKind;
+    use super::super::CamPos;
+
+    const GOLDEN: &str = "src/golden/headless.png";//rendered with the software adapter from the tiles below
+    const SAMPLES: i64 = 240;//samples per degree of the made up tiles
+    const TOLERANCE: u8 = 16;//per channel, gpus and drivers round a little differently
+    const MAX_DIFFERING: f64 = 0.005;//fraction of pixels allowed past the tolerance
+
+    fn write_tiles(dir: &Path) {
+        //rolling hills falling into the sea to the west, as a function of the global sample so the tiles meet
+        fs::create_dir_all(dir).unwrap();
+        for lat in 54..=56 {
+            for long in -6..=-4 {
+                let mut bytes = Vec::with_capacity(((SAMPLES + 1) * (SAMPLES + 1) * 2) as usize);
+                for z in 0..=SAMPLES {
+                    for x in 0..=SAMPLES {
+                        let (gx, gz) = ((long * SAMPLES + x) as f64, ((lat + 1) * SAMPLES - z) as f64);
+                        let height = 300.0 * (gx * 0.05).sin() * (gz * 0.07).cos() + 2.0 * (gx + 4.6 * SAMPLES as f64);
+                        bytes.extend_from_slice(&(height.round() as i16).to_be_bytes());
+                    }
+                }
+                let name = format!("N{:02}W{:03}.hgt", lat, -long);
+                fs::write(dir.join(name), bytes).unwrap();
+            }
+        }
+    }
+
+    fn read_png(path: &Path) -> (u32, u32, Vec<u8>) {
+        let decoder = png::Decoder::new(File::open(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        pixels.truncate(info.buffer_size());
+        (info.width, info.height, pixels)
+    }
+
+    #[test]
+    fn matches_the_golden_image() {
+        let dir = env::temp_dir().join(format!("threedcube-golden-{}", std::process::id()));
+        write_tiles(&dir);
+        let settings = Settings {
+            data_dir: dir.clone(),
+            source: SourceKind::Hgt,
+            width: 160,
+            height: 120,
+            force_fallback: true,
+            camera: CamPos { x: 0.0, y: 25.0, z: 40.0 },//render units are 463 m at 240 samples per degree
+            camlook: CamPos { x: 0.0, y: 0.0, z: -20.0 },
+            ..Settings::default()
+        };
+        let rendered = dir.join("headless.png");
+        match render_png(&rendered, &settings) {
+            Ok(()) => {}
+            Err(e) if e.starts_with("no graphics adapter") => {
+                eprintln!("skipping the golden image, {}", e);
+                return;
+            }
+            Err(e) => panic!("{}", e),
+        }
+        let (width, height, expected) = read_png(Path::new(GOLDEN));
+        let (got_width, got_height, got) = read_png(&rendered);
+        assert_eq!((got_width, got_height), (width, height));
+        let differing = expected.chunks(4).zip(got.chunks(4))
+            .filter(|(a, b)| a.iter().zip(b.iter()).any(|(a, b)| a.abs_diff(*b) > TOLERANCE))
+            .count();
+        let fraction = differing as f64 / (width * height) as f64;
+        assert!(fraction <= MAX_DIFFERING, "{} of {} pixels differ from {}, the render is at {}", differing, width * height, GOLDEN, rendered.display());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
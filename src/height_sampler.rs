@@ -0,0 +1,215 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use super::tiles::wrap_long;
+use super::tile_cache::{TileCache, TileData, TileKey};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {//how heights between srtm samples are estimated
+    Bilinear,
+    Bicubic,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bilinear" => Ok(Interpolation::Bilinear),
+            "bicubic" => Ok(Interpolation::Bicubic),
+            _ => Err(format!("unknown interpolation '{}', expected bilinear or bicubic", s)),
+        }
+    }
+}
+
+pub struct HeightSampler<'a> {//reads heights by global sample index so lookups can cross tile boundaries
+    tiles: &'a mut TileCache,
+    resolution: u32,//samples per degree
+    last: Option<(TileKey, Option<Arc<TileData>>)>,//tile used by the previous lookup, neighbouring samples usually share it
+}
+
+impl<'a> HeightSampler<'a> {
+    pub fn new(tiles: &'a mut TileCache, resolution: u32) -> Self {
+        Self { tiles, resolution, last: None }
+    }
+
+    pub fn samples_per_degree(&self) -> i64 {
+        self.resolution as i64
+    }
+
+    pub fn sample(&mut self, gx: i64, gz: i64) -> Option<f32> {
+        //gx counts samples east of longitude 0 and gz counts samples south of the equator
+        self.with_tile(gx, gz, |data, x, z| data.get(x, z))
+    }
+
+    fn with_tile<T>(&mut self, gx: i64, gz: i64, read: impl Fn(&TileData, u32, u32) -> T) -> Option<T> {
+        let n = self.samples_per_degree();
+        if let Some((key, Some(data))) = &self.last {
+            //the last row and column of a tile are the first of its neighbours so the current tile can answer for them
+            let ix = gx - key.long as i64 * n;
+            let iz = gz + (key.lat as i64 + 1) * n;
+            if (0..=n).contains(&ix) && (0..=n).contains(&iz) {
+                return Some(read(data, ix as u32, iz as u32));
+            }
+        }
+        let key = TileKey {
+            lat: -(gz.div_euclid(n) as i32) - 1,
+            long: wrap_long(gx.div_euclid(n) as i32),
+            resolution: self.resolution,
+        };
+        if self.last.as_ref().map(|(k, _)| *k) != Some(key) {
+            self.last = Some((key, self.tiles.try_get(key)));
+        }
+        match &self.last {
+            Some((_, Some(data))) => Some(read(data, gx.rem_euclid(n) as u32, gz.rem_euclid(n) as u32)),
+            _ => None,//tile still loading
+        }
+    }
+
+    pub fn height_at(&mut self, lat: f64, long: f64, interpolation: Interpolation) -> Option<f32> {
+        //height at any latitude and longitude, None while the tiles needed are still loading
+        let n = self.samples_per_degree() as f64;
+        let fx = long * n;
+        let fz = -lat * n;
+        let x0 = fx.floor() as i64;
+        let z0 = fz.floor() as i64;
+        let tx = (fx - x0 as f64) as f32;
+        let tz = (fz - z0 as f64) as f32;
+        match interpolation {
+            Interpolation::Bilinear => {
+                let h00 = self.sample(x0, z0)?;
+                let h10 = self.sample(x0 + 1, z0)?;
+                let h01 = self.sample(x0, z0 + 1)?;
+                let h11 = self.sample(x0 + 1, z0 + 1)?;
+                let top = h00 + (h10 - h00) * tx;
+                let bottom = h01 + (h11 - h01) * tx;
+                Some(top + (bottom - top) * tz)
+            }
+            Interpolation::Bicubic => {
+                let mut rows = [0f32; 4];
+                for (j, row) in rows.iter_mut().enumerate() {
+                    let z = z0 + j as i64 - 1;
+                    let mut p = [0f32; 4];
+                    for (i, value) in p.iter_mut().enumerate() {
+                        *value = self.sample(x0 + i as i64 - 1, z)?;
+                    }
+                    *row = catmull_rom(p, tx);
+                }
+                Some(catmull_rom(rows, tz))
+            }
+        }
+    }
+}
+
+fn catmull_rom(p: [f32; 4], t: f32) -> f32 {
+    //cubic through p[1] and p[2] using p[0] and p[3] for the slopes
+    let a = -0.5 * p[0] + 1.5 * p[1] - 1.5 * p[2] + 0.5 * p[3];
+    let b = p[0] - 2.5 * p[1] + 2.0 * p[2] - 0.5 * p[3];
+    let c = -0.5 * p[0] + 0.5 * p[2];
+    ((a * t + b) * t + c) * t + p[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::thread;
+    use std::time::Duration;
+    use super::super::elevation::{ElevationSource, RawTile};
+    use super::super::void_fill::VoidFill;
+
+    const N: u32 = 4;//samples per degree, small enough to write the expected heights by hand
+
+    struct Plane;//height is a plane over the global sample grid, so every tile edge must line up
+
+    fn plane(gx: i64, gz: i64) -> f32 {
+        gx as f32 + 100.0 * gz as f32
+    }
+
+    impl ElevationSource for Plane {
+        fn load(&self, lat: i32, long: i32) -> io::Result<Option<RawTile>> {
+            let extent = N + 1;
+            let (x0, z0) = (long as i64 * N as i64, -(lat as i64 + 1) * N as i64);
+            let heights = (0..extent as i64).flat_map(|z| (0..extent as i64).map(move |x| plane(x0 + x, z0 + z))).collect();
+            Ok(Some(RawTile { extent, heights, voids: vec![false; (extent * extent) as usize] }))
+        }
+
+        fn samples_per_degree(&self, _lat: i32, _long: i32) -> Option<u32> {
+            Some(N)
+        }
+    }
+
+    fn height(tiles: &mut TileCache, lat: f64, long: f64, interpolation: Interpolation) -> f32 {
+        //asks again as the tiles it needs arrive, a lookup stops at the first one still loading
+        for _ in 0..1000 {
+            if let Some(height) = HeightSampler::new(tiles, N).height_at(lat, long, interpolation) {
+                return height;
+            }
+            while tiles.pending() > 0 {
+                tiles.poll();
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        panic!("tiles around {} {} never loaded", lat, long);
+    }
+
+    fn expected(lat: f64, long: f64) -> f32 {
+        (long * N as f64) as f32 + 100.0 * (-lat * N as f64) as f32
+    }
+
+    fn cache() -> TileCache {
+        TileCache::new(Arc::new(Plane), VoidFill::Nearest, 2, 32)
+    }
+
+    #[test]
+    fn samples_are_hit_exactly() {
+        let mut tiles = cache();
+        for interpolation in [Interpolation::Bilinear, Interpolation::Bicubic] {
+            for (lat, long) in [(55.25, -4.5), (55.0, -4.0), (0.75, 0.25), (-33.5, 151.75)] {
+                assert_eq!(height(&mut tiles, lat, long, interpolation), expected(lat, long), "{} {}", lat, long);
+            }
+        }
+    }
+
+    #[test]
+    fn midpoints_across_tile_seams() {
+        //an eighth of a degree either side of a whole degree falls half way between the edge samples of two tiles
+        let mut tiles = cache();
+        for interpolation in [Interpolation::Bilinear, Interpolation::Bicubic] {
+            for (lat, long) in [(55.125, -4.125), (55.875, -4.125), (55.875, -3.875), (54.875, -3.875)] {
+                let got = height(&mut tiles, lat, long, interpolation);
+                assert!((got - expected(lat, long)).abs() < 1e-3, "{} {} {:?} got {} expected {}", lat, long, interpolation, got, expected(lat, long));
+            }
+        }
+    }
+
+    #[test]
+    fn southern_and_western_hemispheres() {
+        //the equator and the prime meridian are seams too, with the tiles on the far side keyed by their south west corner
+        let mut tiles = cache();
+        for interpolation in [Interpolation::Bilinear, Interpolation::Bicubic] {
+            for (lat, long) in [(-0.125, -0.125), (0.125, 0.125), (-0.125, 0.125), (-12.375, -77.625), (-1.0, -1.0)] {
+                let got = height(&mut tiles, lat, long, interpolation);
+                assert!((got - expected(lat, long)).abs() < 1e-3, "{} {} {:?} got {} expected {}", lat, long, interpolation, got, expected(lat, long));
+            }
+        }
+    }
+
+    #[test]
+    fn shared_edge_row_reads_the_same_from_either_tile() {
+        //the south edge of N55 is the north edge of N54, whichever tile the sampler last used
+        let mut tiles = cache();
+        height(&mut tiles, 55.5, -4.5, Interpolation::Bicubic);
+        height(&mut tiles, 54.5, -4.5, Interpolation::Bicubic);
+        let mut sampler = HeightSampler::new(&mut tiles, N);
+        let (gx, gz) = (-4 * N as i64 - 2, -55 * N as i64);
+        assert_eq!(sampler.sample(gx, gz - 1), Some(plane(gx, gz - 1)));//inside N55
+        assert_eq!(sampler.sample(gx, gz), Some(plane(gx, gz)));//edge, answered by N55
+        assert_eq!(sampler.sample(gx, gz + 1), Some(plane(gx, gz + 1)));//inside N54
+        assert_eq!(sampler.sample(gx, gz), Some(plane(gx, gz)));//edge, answered by N54
+    }
+
+    #[test]
+    fn loading_tiles_give_none() {
+        let mut tiles = cache();
+        assert_eq!(HeightSampler::new(&mut tiles, N).height_at(10.5, 10.5, Interpolation::Bilinear), None);
+    }
+}
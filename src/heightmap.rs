@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use bytemuck::cast_slice;
+use super::tile_cache::{TileData, TileKey};
+
+pub const LAYERS: usize = 9;//the current tile and the eight around it, north west first and row by row
+
+pub struct Heightmap {//srtm tiles around the current one as textures the vertex shader reads heights from
+    heights: wgpu::Texture,//r32float metres, one layer per tile
+    voids: wgpu::Texture,//r8uint, 1 where the height was filled in
+    resolution: u32,//samples per degree, each layer is one more than this along a side
+    layers: [Option<TileKey>; LAYERS],//tile uploaded to each layer
+}
+
+impl Heightmap {
+    pub fn new(device: &wgpu::Device, resolution: u32) -> Self {
+        let size = wgpu::Extent3d { width: resolution + 1, height: resolution + 1, depth_or_array_layers: LAYERS as u32 };
+        let texture = |label, format| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        Self {
+            heights: texture("Height Texture", wgpu::TextureFormat::R32Float),
+            voids: texture("Void Texture", wgpu::TextureFormat::R8Uint),
+            resolution,
+            layers: [None; LAYERS],
+        }
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    pub fn views(&self) -> (wgpu::TextureView, wgpu::TextureView) {
+        let array = wgpu::TextureViewDescriptor { dimension: Some(wgpu::TextureViewDimension::D2Array), ..Default::default() };
+        (self.heights.create_view(&array), self.voids.create_view(&array))
+    }
+
+    pub fn upload(&mut self, queue: &wgpu::Queue, tiles: &[(TileKey, Option<Arc<TileData>>)]) -> u32 {
+        //copies tiles that are not on the gpu yet, returns a bit per layer that holds the tile it should
+        //so layers still waiting for their tile read as sea level rather than whatever was there before
+        let mut loaded = 0;
+        for (layer, (key, data)) in tiles.iter().enumerate() {
+            if self.layers[layer] != Some(*key) {
+                let Some(data) = data else { continue };
+                let voids: Vec<u8> = data.voids().iter().map(|&v| v as u8).collect();
+                self.write_layer(queue, &self.heights, layer, cast_slice(data.heights()), 4);
+                self.write_layer(queue, &self.voids, layer, &voids, 1);
+                self.layers[layer] = Some(*key);
+            }
+            loaded |= 1 << layer;
+        }
+        loaded
+    }
+
+    fn write_layer(&self, queue: &wgpu::Queue, texture: &wgpu::Texture, layer: usize, data: &[u8], texel_bytes: u32) {
+        let size = self.resolution + 1;
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(size * texel_bytes), rows_per_image: Some(size) },
+            wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+        );
+    }
+}
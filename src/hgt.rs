@@ -0,0 +1,81 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub struct Hgt {//raw heights of one hgt file, extent samples per side row by row from the north west corner
+    pub extent: u32,
+    pub data: Vec<i16>,
+}
+
+impl Hgt {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        //any square grid of big endian i16 samples, 3601 for srtm1 and 1201 for srtm3
+        let bytes = fs::read(path)?;
+        let extent = extent_from_len(bytes.len() as u64).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{} bytes is not a square grid of 16 bit samples", bytes.len()))
+        })?;
+        let data = bytes.chunks_exact(2).map(|b| i16::from_be_bytes([b[0], b[1]])).collect();
+        Ok(Self { extent, data })
+    }
+}
+
+pub fn samples_per_degree<P: AsRef<Path>>(path: P) -> Option<u32> {
+    //resolution of a tile from the file size alone without reading it
+    let len = fs::metadata(path).ok()?.len();
+    extent_from_len(len).map(|extent| extent - 1)
+}
+
+fn extent_from_len(len: u64) -> Option<u32> {
+    if !len.is_multiple_of(2) {
+        return None;
+    }
+    let samples = len / 2;
+    let extent = (samples as f64).sqrt().round() as u64;
+    if extent >= 2 && extent * extent == samples {
+        Some(extent as u32)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn extents_of_srtm1_and_srtm3() {
+        assert_eq!(extent_from_len(3601 * 3601 * 2), Some(3601));
+        assert_eq!(extent_from_len(1201 * 1201 * 2), Some(1201));
+        assert_eq!(extent_from_len(2 * 2 * 2), Some(2));
+    }
+
+    #[test]
+    fn invalid_lengths_are_rejected() {
+        assert_eq!(extent_from_len(0), None);
+        assert_eq!(extent_from_len(2), None);//a single sample is not a tile
+        assert_eq!(extent_from_len(3601 * 3601 * 2 + 1), None);//odd byte count
+        assert_eq!(extent_from_len(3601 * 3601 * 2 + 2), None);//not square
+        assert_eq!(extent_from_len(3601 * 3600 * 2), None);
+    }
+
+    #[test]
+    fn resolution_and_samples_from_a_file() {
+        //a 3x3 tile, 2 samples per degree, big endian with a void in the middle
+        let path = env::temp_dir().join(format!("threedcube-test-{}.hgt", std::process::id()));
+        let samples: [i16; 9] = [1, 2, 3, -4, -32768, 300, 7, 8, 9];
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+        fs::write(&path, bytes).unwrap();
+        let resolution = samples_per_degree(&path);
+        let hgt = Hgt::from_file(&path);
+        fs::write(&path, [0u8; 7]).unwrap();
+        let invalid = Hgt::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(resolution, Some(2));
+        let hgt = hgt.unwrap();
+        assert_eq!(hgt.extent, 3);
+        assert_eq!(hgt.data, samples);
+        assert!(matches!(invalid, Err(e) if e.kind() == io::ErrorKind::InvalidData));
+        assert_eq!(samples_per_degree(&path), None);//missing file
+    }
+}
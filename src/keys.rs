@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use winit::event::VirtualKeyCode;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {//everything a key can do, escape always quits and cannot be rebound
+    Forward,//fly along the heading
+    Back,
+    Up,//straight up or down whatever the camera is pointing at
+    Down,
+    YawLeft,
+    YawRight,
+    PitchUp,
+    PitchDown,
+    RollLeft,
+    RollRight,
+    Faster,
+    Slower,
+    Level,//wings level and the nose on the horizon
+    Pilot,//switch between flying the aircraft and the free camera
+    LessDetail,
+    MoreDetail,
+    PlotType,
+    Interpolation,
+    Voids,
+    Minimise,
+    SunLeft,
+    SunRight,
+    SunUp,
+    SunDown,
+    ColourMode,
+    ColourRamp,
+}
+
+impl Action {
+    pub fn is_held(self) -> bool {
+        //acts every frame for as long as its key is down rather than once per press
+        use Action::*;
+        matches!(self, Forward | Back | Up | Down | YawLeft | YawRight | PitchUp | PitchDown | RollLeft | RollRight | Faster | Slower)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyBindings {
+    keys: HashMap<VirtualKeyCode, Action>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use Action::*;
+        use VirtualKeyCode as K;
+        let keys = [
+            (K::W, Forward), (K::S, Back), (K::PageUp, Up), (K::PageDown, Down),
+            (K::A, YawLeft), (K::D, YawRight), (K::Up, PitchUp), (K::Down, PitchDown), (K::Q, RollLeft), (K::E, RollRight),
+            (K::Equals, Faster), (K::Minus, Slower), (K::Home, Level), (K::P, Pilot),
+            (K::R, LessDetail), (K::F, MoreDetail),
+            (K::Space, PlotType), (K::I, Interpolation), (K::V, Voids), (K::LControl, Minimise),
+            (K::J, SunLeft), (K::L, SunRight), (K::Y, SunUp), (K::H, SunDown),
+            (K::C, ColourMode), (K::G, ColourRamp),
+        ];
+        Self { keys: keys.into_iter().collect() }
+    }
+}
+
+impl KeyBindings {
+    pub fn action(&self, key: VirtualKeyCode) -> Option<Action> {
+        self.keys.get(&key).copied()
+    }
+
+    pub fn rebind(&mut self, bindings: &HashMap<Action, VirtualKeyCode>) -> Result<(), String> {
+        //the actions given move to their new keys, so two actions can swap keys in one go
+        self.keys.retain(|_, action| !bindings.contains_key(action));
+        for (action, key) in bindings {
+            if *key == VirtualKeyCode::Escape {
+                return Err(format!("escape quits and cannot be bound to {:?}", action));
+            }
+            if let Some(other) = self.keys.insert(*key, *action) {
+                return Err(format!("{:?} is bound to both {:?} and {:?}", key, other, action));
+            }
+        }
+        Ok(())
+    }
+}
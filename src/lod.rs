@@ -0,0 +1,40 @@
+use super::CamPos;
+use super::coords::{Frame, RENDER_BASE_HEIGHT};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {//axis aligned box in render space
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Bounds {
+    pub fn distance_to(&self, point: CamPos) -> f32 {
+        //0 inside the box
+        let p = [point.x, point.y, point.z];
+        let squared: f32 = (0..3).map(|i| {
+            let d = (self.min[i] - p[i]).max(p[i] - self.max[i]).max(0.0);
+            d * d
+        }).sum();
+        squared.sqrt()
+    }
+}
+
+pub fn pixels_per_unit(viewport_height: u32, fov: f32) -> f32 {
+    //screen pixels covered by one render unit one unit away from the camera, fov in radians
+    viewport_height as f32 / (2.0 * (fov / 2.0).tan())
+}
+
+pub fn too_coarse(error: f32, distance: f32, pixels_per_unit: f32, max_pixels: f32) -> bool {
+    //true when an error in render units shows as more than max_pixels on screen at this distance
+    error * pixels_per_unit / distance.max(f32::EPSILON) > max_pixels
+}
+
+pub fn chunk_bounds(translation: [f32; 2], span: f32, heights: (f32, f32), frame: &Frame) -> Bounds {
+    //where a chunk ends up once its model matrix is applied, span is its width in samples and heights the lowest and highest in metres
+    let east_west = frame.east_west_scale();
+    let y = |h: f32| RENDER_BASE_HEIGHT + h * frame.vertical_scale;
+    Bounds {
+        min: [translation[0] * east_west, y(heights.0), translation[1]],
+        max: [(translation[0] + span) * east_west, y(heights.1), translation[1] + span],
+    }
+}
@@ -1,4 +1,5 @@
 mod common;
+use std::process;
 
 
 fn main(){
@@ -7,7 +8,25 @@ fn main(){
     //create vertex data from common rs file and using the function from mathfunc.rs file
     //let vertex_data = common::create_vertices(colormap_name, 0.0, 3600.0, 0.0, 3600.0, 30, 30, 2.0, 0.9);//set scale to two and aspect ratio to .7 to .9 for more precise
     //let light_data = common::light([1.0, 1.0, 1.0], 0.1, 0.8, 0.4, 30.0, 1);//1,1,1 for specular light color and set light intensity
-    common::run();//run function run() from common.rs file
+    let settings = match common::cli::parse(std::env::args().skip(1)) {//command line options, see --help
+        Ok(common::cli::Command::Run(settings)) => settings,
+        Ok(common::cli::Command::Help) => {
+            println!("{}", common::cli::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, common::cli::USAGE);
+            process::exit(2);
+        }
+    };
+    let result = match &settings.headless {
+        Some(path) => common::headless::render_png(path, &settings),//one frame to a png without a window
+        None => common::run(&settings),//run function run() from common.rs file
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
 
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,60 @@
+use std::collections::VecDeque;
+
+pub const MAX_LEVEL: u32 = 12;//a level 12 chunk of 241 samples is almost 1000 km across at full srtm resolution
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Node {//a square of terrain drawn as one chunk, each level up covers twice the samples with the same grid
+    pub level: u32,
+    pub x: i64,//samples at the resolution being rendered east of the current tile's north west corner
+    pub z: i64,//and south of it
+}
+
+impl Node {
+    pub fn span(&self, chunk_span: u32) -> i64 {
+        //samples from one side of the node to the other, chunk_span is a level 0 chunk's
+        (chunk_span as i64) << self.level
+    }
+
+    pub fn scale(&self) -> u32 {
+        //how much bigger than a level 0 chunk the grid is drawn
+        1 << self.level
+    }
+
+    fn children(&self, chunk_span: u32) -> [Node; 4] {
+        let half = self.span(chunk_span) / 2;
+        let level = self.level - 1;
+        [(0, 0), (half, 0), (0, half), (half, half)].map(|(dx, dz)| Node { level, x: self.x + dx, z: self.z + dz })
+    }
+}
+
+pub fn roots(centre: [f64; 2], radius: [f64; 2], chunk_span: u32) -> Vec<Node> {
+    //nodes of the first level at least as wide as the view radius, covering the view around the centre
+    //they sit on a grid fixed to the tile so moving around keeps the same nodes and their measurements
+    let widest = radius[0].max(radius[1]);
+    let level = (0..MAX_LEVEL).find(|&level| ((chunk_span as i64) << level) as f64 >= widest).unwrap_or(MAX_LEVEL);
+    let span = (chunk_span as i64) << level;
+    let range = |c: f64, r: f64| ((c - r) / span as f64).floor() as i64..=((c + r) / span as f64).floor() as i64;
+    let mut roots = vec![];
+    for i in range(centre[0], radius[0]) {
+        for j in range(centre[1], radius[1]) {
+            roots.push(Node { level, x: i * span, z: j * span });
+        }
+    }
+    roots
+}
+
+pub fn select(roots: Vec<Node>, chunk_span: u32, max_chunks: usize, mut split: impl FnMut(Node) -> bool) -> Vec<Node> {
+    //the chunks to draw, nodes are split into their four children while split says they are too coarse
+    //breadth first so when the budget runs out every part of the view has been refined as evenly as it can be
+    let mut queue: VecDeque<Node> = roots.into();
+    let mut chunks = vec![];
+    while let Some(node) = queue.pop_front() {
+        if node.level > 0 && chunks.len() + queue.len() + 4 <= max_chunks && split(node) {
+            queue.extend(node.children(chunk_span));
+        } else {
+            chunks.push(node);
+        }
+    }
+    chunks.truncate(max_chunks);
+    chunks
+}
@@ -0,0 +1,258 @@
+use std::env;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+use super::CamPos;
+use super::color_ramp::{self, ColorRamp};
+use super::coords::{GeoPoint, METRES_PER_DEGREE, START_POSITION_ENV};
+use super::elevation::{SourceKind, SOURCE_ENV};
+use super::flight::Aircraft;
+use super::height_sampler::Interpolation;
+use super::keys::KeyBindings;
+use super::shading::ColourMode;
+use super::sun::Sun;
+use super::taws::TawsConfig;
+use super::tiles::{DATA_DIR_ENV, DEFAULT_DATA_DIR};
+use super::void_fill::{VoidFill, VOID_FILL_ENV};
+
+pub const MAX_CHUNKS: u32 = 1024;//every chunk drawn is an instance of the grid with its own model matrix
+pub const MAX_CHUNK_SIZE: u32 = 2049;//keeps a chunk's vertex buffer well under the default buffer size limit
+pub const MAX_WINDOW_SIZE: u32 = 16384;
+pub const MAX_LOOK_AHEAD: f32 = 300.0;//seconds, every second ahead is a height lookup each frame
+
+#[derive(Clone, Debug)]
+pub struct Settings {//everything chosen at startup, from the command line or the environment
+    pub start: GeoPoint,//where the camera starts
+    pub view_distance: f32,//km from the camera the terrain is drawn out to
+    pub max_chunks: u32,//most quadtree chunks drawn at once, bounds the vertices drawn
+    pub chunksize: u32,//vertices along each side of a chunk, level 0 chunks are this many samples across
+    pub water_level: f32,//metres
+    pub vertical_exaggeration: f32,
+    pub colour_height_max: f32,//metres at the top of the colour bands
+    pub colour_thresholds: Vec<f32>,//band edges from 0 to 1 of colour_height_max
+    pub colours: Vec<[f32; 3]>,//one per band, the first is water
+    pub colour_mode: ColourMode,
+    pub ramp: String,//colour ramp to start with, bands, one of the built in ramps or a loaded file's name
+    pub ramp_files: Vec<ColorRamp>,//loaded from gdal colour relief files
+    pub taws_caution: f32,//feet below the camera where taws colouring turns amber
+    pub taws_warning: f32,//feet below the camera where it turns red
+    pub taws_alerts: TawsConfig,//seconds ahead the flight path is checked and the times to impact that alert
+    pub camera: CamPos,//render units relative to the terrain centre
+    pub camlook: CamPos,//the camera starts pointing here with its wings level
+    pub speed: f32,//metres per second the camera flies along its heading
+    pub mouse_sensitivity: f32,//degrees the camera turns per pixel the mouse moves
+    pub aircraft: Aircraft,//performance the flight model flies with
+    pub fly: bool,//start flying the aircraft rather than the free camera
+    pub min_clearance: f32,//metres above the ground or water below which a warning is raised
+    pub floor: bool,//hold the camera at the minimum clearance rather than let it through the ground
+    pub data_dir: PathBuf,
+    pub source: SourceKind,
+    pub void_fill: VoidFill,
+    pub interpolation: Interpolation,
+    pub level_of_detail: u32,//finest level of detail any chunk is drawn at
+    pub lod_steps: Vec<u32>,//samples between vertices at each level of detail
+    pub pixel_error: f32,//screen pixels a chunk's grid may be off the ground before a finer one is used
+    pub fov: f32,//vertical field of view in degrees
+    pub near: f32,//clipping planes in render units
+    pub far: f32,
+    pub keys: KeyBindings,
+    pub sun: Sun,
+    pub width: u32,//window or image size in pixels
+    pub height: u32,
+    pub headless: Option<PathBuf>,//render one frame to this png instead of opening a window
+    pub force_fallback: bool,//software adapter even when there is a gpu
+    pub shader: Option<PathBuf>,//dev mode, wgsl read from here and reloaded when it changes
+}
+
+impl Settings {
+    pub fn max_view_distance(&self) -> f32 {
+        //km, only the 3x3 tiles around the current one are loaded so past one tile's width the terrain is sea level
+        //tiles narrow east to west towards the poles, so the start tile's poleward edge sets it
+        let tile = self.start.lat.floor();
+        let poleward = tile.abs().max((tile + 1.0).abs()).min(90.0);
+        (METRES_PER_DEGREE * poleward.to_radians().cos() / 1000.0) as f32
+    }
+
+    pub fn far_for(&self, metres_per_sample: f64) -> f32 {
+        //render units out to the view distance, one render unit is one sample
+        (self.view_distance as f64 * 1000.0 / metres_per_sample) as f32
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        //the environment variables from before the command line still work as defaults
+        Self {
+            start: env_setting(START_POSITION_ENV).unwrap_or(GeoPoint { lat: 55.5, long: -4.5 }),//middle of N55W005 scotland
+            view_distance: 30.0,
+            max_chunks: 128,
+            chunksize: 241,
+            water_level: 1.0,
+            vertical_exaggeration: 3.0,
+            colour_height_max: 1350.0,//just above ben nevis
+            colour_thresholds: vec![0.0, 0.3, 0.35, 0.7, 0.9, 1.0],
+            colours: vec![
+                [0.055, 0.529, 0.8],//water
+                [0.761, 0.698, 0.502],//sand
+                [0.204, 0.549, 0.192],//grass
+                [0.353, 0.302, 0.255],//rock
+                [1.0, 0.98, 0.98],//snow
+            ],
+            colour_mode: ColourMode::Tinted,
+            ramp: "bands".to_string(),
+            ramp_files: Vec::new(),
+            taws_caution: 1000.0,
+            taws_warning: 500.0,
+            taws_alerts: TawsConfig::default(),
+            camera: CamPos { x: 0.0, y: 100.0, z: 200.0 },
+            camlook: CamPos { x: 0.0, y: 100.0, z: -30.0 },
+            speed: 100.0,
+            mouse_sensitivity: 0.2,
+            aircraft: Aircraft::builtin("c172").expect("c172 is built in"),
+            fly: true,
+            min_clearance: 10.0,
+            floor: true,
+            data_dir: env::var_os(DATA_DIR_ENV).map(PathBuf::from).unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR)),
+            source: env_setting(SOURCE_ENV).unwrap_or_default(),
+            void_fill: env_setting(VOID_FILL_ENV).unwrap_or_default(),
+            interpolation: Interpolation::Bilinear,
+            level_of_detail: 0,
+            lod_steps: vec![1, 2, 3, 4, 5, 6, 8, 10],
+            pixel_error: 2.0,
+            fov: 72.0,
+            near: 0.1,
+            far: 1000.0,
+            keys: KeyBindings::default(),
+            sun: Sun::default(),
+            width: 800,
+            height: 600,
+            headless: None,
+            force_fallback: false,
+            shader: None,
+        }
+    }
+}
+
+fn env_setting<T: FromStr>(name: &str) -> Option<T>
+where T::Err: Display {
+    match env::var(name).ok()?.parse() {
+        Ok(value) => Some(value),
+        Err(err) => {
+            eprintln!("ignoring {}: {}", name, err);
+            None
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        //catches settings that would panic or draw nothing once rendering starts
+        let max_view_distance = self.max_view_distance();
+        if !(self.view_distance > 0.0 && self.view_distance <= max_view_distance) {
+            return Err(format!("view distance must be more than 0 and at most {:.0} km, one tile's width at latitude {} as only the tiles next to the current one are loaded, got {}",
+                max_view_distance.floor(), self.start.lat, self.view_distance));
+        }
+        //the view is covered by at most 3x3 quadtree roots before anything is split
+        if !(9..=MAX_CHUNKS).contains(&self.max_chunks) {
+            return Err(format!("most chunks drawn must be between 9 and {}, got {}", MAX_CHUNKS, self.max_chunks));
+        }
+        if !(2..=MAX_CHUNK_SIZE).contains(&self.chunksize) {
+            return Err(format!("chunk size must be between 2 and {} samples, got {}", MAX_CHUNK_SIZE, self.chunksize));
+        }
+        if !self.water_level.is_finite() {
+            return Err(format!("water level {} is not a number of metres", self.water_level));
+        }
+        if !(self.vertical_exaggeration.is_finite() && self.vertical_exaggeration > 0.0) {
+            return Err(format!("vertical exaggeration must be above 0, got {}", self.vertical_exaggeration));
+        }
+        if !(self.colour_height_max.is_finite() && self.colour_height_max > 0.0) {
+            return Err(format!("colour height max must be above 0 metres, got {}", self.colour_height_max));
+        }
+        if self.colours.len() < 2 {
+            return Err("at least two colour bands are needed, water and land".to_string());
+        }
+        if self.colour_thresholds.len() != self.colours.len() + 1 {
+            return Err(format!("{} colour bands need {} thresholds, got {}", self.colours.len(), self.colours.len() + 1, self.colour_thresholds.len()));
+        }
+        if self.colour_thresholds.first() != Some(&0.0) || self.colour_thresholds.last() != Some(&1.0)
+            || self.colour_thresholds.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(format!("colour thresholds must rise from 0 to 1, got {:?}", self.colour_thresholds));
+        }
+        if self.colours.iter().flatten().any(|c| !(0.0..=1.0).contains(c)) {
+            return Err("colour components must be between 0 and 1".to_string());
+        }
+        if self.colour_height_max <= self.water_level {
+            return Err(format!("colour height max {} must be above the water level {}", self.colour_height_max, self.water_level));
+        }
+        let ramps = color_ramp::ramps(self);
+        if !ramps.iter().any(|r| r.name() == self.ramp) {
+            let names: Vec<&str> = ramps.iter().map(|r| r.name()).collect();
+            return Err(format!("unknown colour ramp '{}', expected one of {}", self.ramp, names.join(", ")));
+        }
+        for (name, p) in [("camera", self.camera), ("look", self.camlook)] {
+            if ![p.x, p.y, p.z].iter().all(|v| v.is_finite()) {
+                return Err(format!("{} position has to be finite", name));
+            }
+        }
+        if self.camera.x == self.camlook.x && self.camera.y == self.camlook.y && self.camera.z == self.camlook.z {
+            return Err("the camera cannot look at its own position".to_string());
+        }
+        if !(self.speed >= 0.0 && self.speed.is_finite()) {
+            return Err(format!("speed must be 0 or more metres per second, got {}", self.speed));
+        }
+        if !(self.mouse_sensitivity > 0.0 && self.mouse_sensitivity.is_finite()) {
+            return Err(format!("mouse sensitivity must be more than 0 degrees per pixel, got {}", self.mouse_sensitivity));
+        }
+        if !(self.min_clearance >= 0.0 && self.min_clearance.is_finite()) {
+            return Err(format!("minimum clearance must be 0 or more metres, got {}", self.min_clearance));
+        }
+        if self.lod_steps.is_empty() || self.lod_steps.contains(&0) {
+            return Err(format!("level of detail steps must all be at least 1, got {:?}", self.lod_steps));
+        }
+        //a step that does not divide the chunk leaves its last row and column short of the edge and gaps skirts cannot hide
+        if let Some(step) = self.lod_steps.iter().find(|&&step| !(self.chunksize - 1).is_multiple_of(step)) {
+            return Err(format!("level of detail step {} does not divide the {} samples across a chunk of size {}, every step has to",
+                step, self.chunksize - 1, self.chunksize));
+        }
+        if self.level_of_detail as usize >= self.lod_steps.len() {
+            return Err(format!("level of detail must be between 0 and {}, got {}", self.lod_steps.len() - 1, self.level_of_detail));
+        }
+        if !(self.pixel_error > 0.0 && self.pixel_error.is_finite()) {
+            return Err(format!("pixel error must be more than 0, got {}", self.pixel_error));
+        }
+        if !(self.fov > 0.0 && self.fov < 180.0) {
+            return Err(format!("field of view must be between 0 and 180 degrees, got {}", self.fov));
+        }
+        if !(self.near > 0.0 && self.far > self.near && self.far.is_finite()) {
+            return Err(format!("clipping planes need 0 < near < far, got near {} far {}", self.near, self.far));
+        }
+        if !self.sun.azimuth.is_finite() || !(0.0..=90.0).contains(&self.sun.elevation) {
+            return Err(format!("sun elevation must be between 0 and 90 degrees, got {}", self.sun.elevation));
+        }
+        if !(0.0..=1.0).contains(&self.sun.ambient) {
+            return Err(format!("ambient light must be between 0 and 1, got {}", self.sun.ambient));
+        }
+        if !(self.taws_warning >= 0.0 && self.taws_caution > self.taws_warning && self.taws_caution.is_finite()) {
+            return Err(format!("taws clearances need 0 <= warning < caution, got caution {} warning {}", self.taws_caution, self.taws_warning));
+        }
+        let alerts = self.taws_alerts;
+        if !(alerts.pull_up > 0.0 && alerts.pull_up <= alerts.caution && alerts.caution <= alerts.look_ahead && alerts.look_ahead <= MAX_LOOK_AHEAD) {
+            return Err(format!("taws alert times need 0 < pull up <= caution <= look ahead <= {} seconds, got pull up {} caution {} look ahead {}",
+                MAX_LOOK_AHEAD, alerts.pull_up, alerts.caution, alerts.look_ahead));
+        }
+        if !(alerts.clearance >= 0.0 && alerts.clearance.is_finite()) {
+            return Err(format!("taws path clearance must be 0 or more metres, got {}", alerts.clearance));
+        }
+        if self.floor && alerts.clearance > self.min_clearance {
+            //the floor lets the aircraft skim the ground at the minimum clearance, any more would alert for good
+            return Err(format!("taws path clearance {} m is more than the {} m minimum clearance the floor holds", alerts.clearance, self.min_clearance));
+        }
+        if !(1..=MAX_WINDOW_SIZE).contains(&self.width) || !(1..=MAX_WINDOW_SIZE).contains(&self.height) {
+            return Err(format!("window size must be between 1 and {} pixels, got {}x{}", MAX_WINDOW_SIZE, self.width, self.height));
+        }
+        if self.source == SourceKind::Hgt && !self.data_dir.is_dir() {
+            return Err(format!("data directory {} does not exist", self.data_dir.display()));
+        }
+        Ok(())
+    }
+}
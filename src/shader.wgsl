@@ -2,27 +2,220 @@
 @binding(0) @group(0) var<uniform> vpMat: mat4x4f; //separate view projection matrix and model matrix
 @group(0) @binding(1)  var<storage> modelMat: array<mat4x4f>;
 
+struct Shading {
+    direction: vec3f, // towards the sun in world space
+    ambient: f32,
+    mode: u32, // 0 elevation, 1 hillshade, 2 slope, 3 aspect, 4 tinted, 5 taws
+    exaggeration: f32, // vertical exaggeration already in the normals
+    altitude: f32, // camera, metres
+    caution: f32, // metres below the camera
+    warning: f32,
+};
+@group(0) @binding(2) var<uniform> shading: Shading;
+
+struct Terrain {
+    tile: i32, // samples along a tile
+    water_level: f32,
+    vertical_scale: f32, // render units per metre
+    east_west: f32,
+    height_max: f32,
+    loaded: u32, // a bit per heights layer holding its tile
+    show_voids: u32,
+    stop_count: u32,
+    normalised: u32, // ramp stops from 0 at the water level to 1 at height_max rather than metres
+    stepped: u32,
+    water: vec4f, // rgb drawn under the water level when w is 1
+};
+@group(1) @binding(0) var<uniform> terrain: Terrain;
+struct Chunk {
+    origin: vec2i, // sample at the chunk's corner from the current tile's north west corner
+    increment: i32, // samples between the chunk's vertices
+    scale: u32, // quadtree chunks double in size each level up
+    skirt_depth: f32, // metres the skirts hang below the chunk edges
+};
+@group(1) @binding(1) var<storage> chunks: array<Chunk>;
+@group(1) @binding(2) var<storage> stops: array<vec4f>; // colour ramp, height then rgb
+@group(1) @binding(3) var heights: texture_2d_array<f32>; // metres, the 3x3 tiles around the current one
+@group(1) @binding(4) var voids: texture_2d_array<u32>;
+
+const VOID_COLOR = vec3(0.8, 0.2, 0.8); // filled in voids when show_voids is on
+
 struct Input {
-    @builtin(instance_index) idx: u32, // added index
-    @location(0) position: vec4f,
-    @location(1) color: vec4f
+    @location(0) position: vec2u, // sample within the chunk
+    @location(1) skirt: u32, // 1 for the copies of the edge vertices hanging down to hide cracks
+    @location(2) chunk: u32, // chunk index, stepped per instance
 };
 
 struct Output {
     @builtin(position) position : vec4f,
     @location(0) vColor: vec4f,
+    @location(1) vNormal: vec3f,
+    @location(2) vHeight: f32, // metres, before the model matrix exaggerates it
 };
 
-@vertex
-fn vs_main(in:Input) -> Output {
+fn layer_texel(sample: vec2i) -> vec3i {
+    // heights layer and texel of a sample counted from the current tile's north west corner, layer -1 when not loaded
+    let tile = vec2i(floor(vec2f(sample) / f32(terrain.tile)));
+    let layer = (tile.y + 1) * 3 + tile.x + 1;
+    if any(abs(tile) > vec2i(1)) || (terrain.loaded & (1u << u32(layer))) == 0u {
+        return vec3i(0, 0, -1);
+    }
+    return vec3i(sample - tile * terrain.tile, layer);
+}
+
+fn height_at(sample: vec2i) -> f32 {
+    // tiles still loading are sea level, anything under the water is flattened for a smooth water line
+    let at = layer_texel(sample);
+    var height = 0.0;
+    if at.z >= 0 {
+        height = textureLoad(heights, at.xy, at.z, 0).r;
+    }
+    if height < terrain.water_level {
+        height = terrain.water_level - 0.01;
+    }
+    return height;
+}
+
+fn is_void(sample: vec2i) -> bool {
+    let at = layer_texel(sample);
+    return at.z >= 0 && textureLoad(voids, at.xy, at.z, 0).r == 1u;
+}
+
+fn ramp_colour(height: f32) -> vec3f {
+    if terrain.water.w > 0.0 && height < terrain.water_level {
+        return terrain.water.rgb;
+    }
+    var h = height;
+    if terrain.normalised == 1u {
+        h = (height - terrain.water_level) / (terrain.height_max - terrain.water_level);
+    }
+    // first stop above the height
+    var above = 0u;
+    for (var i = 0u; i < terrain.stop_count; i++) {
+        if stops[i].x <= h {
+            above = i + 1u;
+        }
+    }
+    if above == 0u {
+        return stops[0].yzw;
+    }
+    let low = stops[above - 1u];
+    if terrain.stepped == 1u || above == terrain.stop_count {
+        return low.yzw;
+    }
+    let high = stops[above];
+    return mix(low.yzw, high.yzw, (h - low.x) / (high.x - low.x));
+}
+
+fn grid_sample(in: Input) -> vec2i {
+    // the sample under a grid vertex counted from the current tile's north west corner
+    return chunks[in.chunk].origin + vec2i(in.position * chunks[in.chunk].scale);
+}
+
+fn displace(in: Input) -> Output {
+    // the grid vertex moved up to the ground with a normal from the neighbouring vertices
+    let sample = grid_sample(in);
+    let height = height_at(sample);
+    let step = chunks[in.chunk].increment;
+    let slope_scale = terrain.vertical_scale / (2.0 * f32(step));
+    let slope_x = (height_at(sample + vec2i(step, 0)) - height_at(sample - vec2i(step, 0))) * slope_scale / terrain.east_west;
+    let slope_z = (height_at(sample + vec2i(0, step)) - height_at(sample - vec2i(0, step))) * slope_scale;
+    let drop = f32(in.skirt) * chunks[in.chunk].skirt_depth;
     var output: Output;
-    output.position = vpMat * modelMat[in.idx] * in.position;
-    output.vColor = in.color;
+    output.position = vpMat * modelMat[in.chunk] * vec4(f32(in.position.x), height - drop, f32(in.position.y), 1.0);
+    output.vNormal = normalize(vec3(-slope_x, 1.0, -slope_z)); // world space, the model matrix has no rotation
+    output.vHeight = height;
     return output;
 }
 
-// fragment shader
+@vertex
+fn vs_main(in: Input) -> Output {
+    var output = displace(in);
+    if terrain.show_voids == 1u && is_void(grid_sample(in)) {
+        output.vColor = vec4(VOID_COLOR, 1.0);
+    } else {
+        output.vColor = vec4(ramp_colour(output.vHeight), 1.0);
+    }
+    return output;
+}
+
+@vertex
+fn vs_lines(in: Input) -> Output {
+    // the wireframe drawn over the terrain is white
+    var output = displace(in);
+    output.vColor = vec4(1.0);
+    return output;
+}
+
+// fragment shader, the colour mode picks between the height colours, the sun and the shape of the ground
+fn hillshade(normal: vec3f) -> f32 {
+    return max(dot(normal, normalize(shading.direction)), 0.0);
+}
+
+fn slope_colour(normal: vec3f) -> vec3f {
+    // degrees of the real ground, green on the flat through yellow at 15 to red from 35
+    let degrees = degrees(atan(length(normal.xz) / (max(normal.y, 1e-4) * shading.exaggeration)));
+    let green = vec3(0.2, 0.6, 0.2);
+    let yellow = vec3(0.95, 0.85, 0.2);
+    let red = vec3(0.8, 0.1, 0.1);
+    if degrees < 15.0 {
+        return mix(green, yellow, degrees / 15.0);
+    }
+    return mix(yellow, red, min((degrees - 15.0) / 20.0, 1.0));
+}
+
+fn aspect_colour(normal: vec3f) -> vec3f {
+    // hue from the compass bearing the slope faces, x is east and z is south, flat ground is grey
+    if length(normal.xz) < 1e-3 {
+        return vec3(0.6);
+    }
+    let hue = fract(atan2(normal.x, -normal.z) / 6.2831853 + 1.0);
+    let rgb = clamp(abs(fract(hue + vec3(1.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0, vec3(0.0), vec3(1.0));
+    return mix(vec3(1.0), rgb, 0.8);
+}
+
+fn taws_colour(height: f32) -> vec3f {
+    // red from the warning clearance up, amber from the caution clearance, green below that and black well below
+    let clearance = shading.altitude - height;
+    if clearance < shading.warning {
+        return vec3(0.9, 0.1, 0.1);
+    }
+    if clearance < shading.caution {
+        return vec3(0.95, 0.65, 0.1);
+    }
+    if clearance < 2.0 * shading.caution {
+        return vec3(0.1, 0.6, 0.2);
+    }
+    return vec3(0.0);
+}
+
 @fragment
-fn fs_main(@location(0) vColor: vec4f) ->  @location(0) vec4f {
-    return vec4(vColor.rgb, 1.0);
-}
\ No newline at end of file
+fn fs_main(@location(0) vColor: vec4f, @location(1) vNormal: vec3f, @location(2) vHeight: f32) ->  @location(0) vec4f {
+    let normal = normalize(vNormal);
+    var colour = vColor.rgb;
+    switch shading.mode {
+        case 0u: {}
+        case 1u: {
+            colour = vec3(hillshade(normal));
+        }
+        case 2u: {
+            colour = slope_colour(normal);
+        }
+        case 3u: {
+            colour = aspect_colour(normal);
+        }
+        case 5u: {
+            colour = taws_colour(vHeight);
+        }
+        default: {
+            colour = vColor.rgb * (shading.ambient + (1.0 - shading.ambient) * hillshade(normal));
+        }
+    }
+    return vec4(colour, 1.0);
+}
+
+@fragment
+fn fs_lines(@location(0) vColor: vec4f, @location(1) vNormal: vec3f, @location(2) vHeight: f32) -> @location(0) vec4f {
+    // the wireframe keeps its own colour whatever the colour mode does to the surface, the other inputs go unused
+    return vColor;
+}
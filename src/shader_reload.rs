@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);//how often the shader file's modified time is checked
+
+pub struct ShaderWatch {//a wgsl file read from disk and read again whenever it changes
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatch {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), modified: None, last_poll: Instant::now() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn read(&mut self) -> Result<String, String> {
+        self.modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        fs::read_to_string(&self.path).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+
+    pub fn poll(&mut self) -> Option<Result<String, String>> {
+        //the new source once the file has changed since it was last read
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok()?;//editors can remove the file while saving
+        if Some(modified) == self.modified {
+            return None;
+        }
+        Some(self.read())
+    }
+}
+
+pub fn compile<T>(device: &wgpu::Device, source: &str, build: impl FnOnce(&wgpu::ShaderModule) -> T) -> Result<T, String> {
+    //validation errors are caught rather than panicking so a typo in the shader does not close the viewer
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Shader From Disk"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let built = build(&module);
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(err) => Err(err.to_string()),
+        None => Ok(built),
+    }
+}
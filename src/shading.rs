@@ -0,0 +1,107 @@
+use std::fmt;
+use std::str::FromStr;
+use bytemuck::{Pod, Zeroable};
+use super::settings::Settings;
+use super::sun::Sun;
+
+pub const METRES_PER_FOOT: f32 = 0.3048;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColourMode {//how the fragment shader colours the terrain, the numbers are the mode in shader.wgsl
+    Elevation = 0,//the height bands without lighting
+    Hillshade = 1,//grey relief lit by the sun
+    Slope = 2,//green on the flat to red on steep ground
+    Aspect = 3,//hue from the compass direction a slope faces
+    Tinted = 4,//height bands lit by the sun
+    Taws = 5,//terrain awareness, red and amber where the ground is close to or above the camera
+}
+
+impl ColourMode {
+    pub fn next(self) -> Self {
+        match self {
+            ColourMode::Elevation => ColourMode::Hillshade,
+            ColourMode::Hillshade => ColourMode::Slope,
+            ColourMode::Slope => ColourMode::Aspect,
+            ColourMode::Aspect => ColourMode::Tinted,
+            ColourMode::Tinted => ColourMode::Taws,
+            ColourMode::Taws => ColourMode::Elevation,
+        }
+    }
+}
+
+impl FromStr for ColourMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "elevation" => Ok(ColourMode::Elevation),
+            "hillshade" => Ok(ColourMode::Hillshade),
+            "slope" => Ok(ColourMode::Slope),
+            "aspect" => Ok(ColourMode::Aspect),
+            "tinted" => Ok(ColourMode::Tinted),
+            "taws" => Ok(ColourMode::Taws),
+            _ => Err(format!("unknown colour mode '{}', expected elevation, hillshade, slope, aspect, tinted or taws", s)),
+        }
+    }
+}
+
+impl fmt::Display for ColourMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColourMode::Elevation => "elevation",
+            ColourMode::Hillshade => "hillshade",
+            ColourMode::Slope => "slope",
+            ColourMode::Aspect => "aspect",
+            ColourMode::Tinted => "tinted",
+            ColourMode::Taws => "taws",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Shading {//everything the fragment shader needs apart from the camera altitude
+    pub sun: Sun,
+    pub mode: ColourMode,
+    pub exaggeration: f32,
+    pub caution: f32,//feet below the camera where taws colouring turns amber
+    pub warning: f32,//feet below the camera where it turns red
+}
+
+impl Shading {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            sun: settings.sun,
+            mode: settings.colour_mode,
+            exaggeration: settings.vertical_exaggeration,
+            caution: settings.taws_caution,
+            warning: settings.taws_warning,
+        }
+    }
+
+    pub fn uniform(&self, altitude: f32) -> ShadingUniform {
+        //altitude is the camera's in metres, it changes every time the camera climbs or descends
+        ShadingUniform {
+            direction: self.sun.direction(),
+            ambient: self.sun.ambient,
+            mode: self.mode as u32,
+            exaggeration: self.exaggeration,
+            altitude,
+            caution: self.caution * METRES_PER_FOOT,
+            warning: self.warning * METRES_PER_FOOT,
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ShadingUniform {//matches the Shading struct in shader.wgsl
+    pub direction: [f32; 3],//towards the sun in world space
+    pub ambient: f32,
+    pub mode: u32,
+    pub exaggeration: f32,//normals are exaggerated with the heights, slope angles take it back out
+    pub altitude: f32,//camera, metres
+    pub caution: f32,//metres
+    pub warning: f32,
+    _padding: [u32; 3],//wgsl rounds the struct up to 16 bytes
+}
@@ -0,0 +1,31 @@
+pub const SUN_STEP: f32 = 5.0;//degrees the sun moves per key press
+
+#[derive(Copy, Clone, Debug)]
+pub struct Sun {//directional light, angles in degrees
+    pub azimuth: f32,//clockwise from north
+    pub elevation: f32,//above the horizon
+    pub ambient: f32,//light reaching slopes facing away from the sun, 0 to 1
+}
+
+impl Default for Sun {
+    fn default() -> Self {
+        //north west at 45 degrees like a standard hillshade
+        Self { azimuth: 315.0, elevation: 45.0, ambient: 0.3 }
+    }
+}
+
+impl Sun {
+    pub fn turn(&mut self, degrees: f32) {
+        self.azimuth = (self.azimuth + degrees).rem_euclid(360.0);
+    }
+
+    pub fn raise(&mut self, degrees: f32) {
+        self.elevation = (self.elevation + degrees).clamp(0.0, 90.0);
+    }
+
+    pub fn direction(&self) -> [f32; 3] {
+        //towards the sun, world x is east, y is up and z is south
+        let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        [azimuth.sin() * elevation.cos(), elevation.sin(), -azimuth.cos() * elevation.cos()]
+    }
+}
@@ -1,233 +1,238 @@
 use bytemuck:: {Pod, Zeroable};
-use srtm::Tile;
-use std::thread;
-use std::sync::mpsc;
-use std::thread::JoinHandle;
-//mod colormap;
+use super::tiles::{wrap_long, DEFAULT_SAMPLES_PER_DEGREE};
+use super::elevation::ElevationSource;
+use super::tile_cache::{TileCache, TileData, TileKey};
+use super::height_sampler::{HeightSampler, Interpolation};
+use super::coords::{Frame, GeoPoint, METRES_PER_DEGREE};
+use super::settings::Settings;
+use super::color_ramp::{self, ColorRamp};
+use super::heightmap;
+use super::quadtree::Node;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+const TILE_WORKERS: usize = 4;//threads loading srtm tiles in the background
+const TILE_CACHE_CAPACITY: usize = 20;//tiles kept in memory, enough for the 3x3 neighbourhood at both resolutions
+const MEASURE_CACHE_CAPACITY: usize = 4096;//quadtree nodes remembered before starting again
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
-pub struct Vertex {//Vertex struct containing color and position
-    pub position: [f32; 3],
-    pub color: [f32; 3],
+pub struct Vertex {//sample offset within a chunk, the vertex shader looks up the height, normal and colour
+    pub position: [u32; 2],
+    pub skirt: u32,//1 for the copy of an edge vertex that hangs down to hide cracks next to coarser chunks
 }
-trait Defaultable {//Default function for creating a thread  with latitude and longitude parameters
-    fn default_with_params(lat: u32,long:u32,minimised:bool) -> Self;
+pub struct GridLevel {//where one level of detail sits in the shared index buffers
+    pub indices: Range<u32>,
+    pub lines: Range<u32>,//wireframe, without the skirt
 }
-struct Threaded {//Struct Threaded containing thread receiver and the thread itself
-    pub refer: std::sync::mpsc::Receiver<Vec<Vec<f32>>>,
-    pub thread:JoinHandle<()>,
+pub struct Grids {//the grid at every level of detail, one after another
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub lines: Vec<u32>,
+    pub levels: Vec<GridLevel>,
+}
+#[derive(Copy, Clone, Debug)]
+pub struct ChunkMeasure {//heights in metres as they are drawn, tiles still loading count as sea level
+    pub min_height: f32,
+    pub max_height: f32,
+    pub error: f32,//furthest the node's grid is from its children's, or from full detail at level 0
+}
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ChunkUniform {//matches the Chunk struct in shader.wgsl
+    pub origin: [i32; 2],//sample at the chunk's corner from the current tile's north west corner, whole so every vertex reads its own sample
+    pub increment: i32,//samples between the chunk's vertices
+    pub scale: u32,//the grid is drawn this many times bigger than a level 0 chunk
+    pub skirt_depth: f32,//metres the skirts hang below the chunk edges
+    _padding: u32,
+}
+impl ChunkUniform {
+    pub fn new(origin: [i32; 2], increment: u32, scale: u32, skirt_depth: f32) -> Self {
+        Self { origin, increment: (increment * scale) as i32, scale, skirt_depth, _padding: 0 }
+    }
+}
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct TerrainUniform {//matches the Terrain struct in shader.wgsl
+    pub tile: i32,//samples along a tile
+    pub water_level: f32,
+    pub vertical_scale: f32,
+    pub east_west: f32,
+    pub height_max: f32,
+    pub loaded: u32,//a bit per heightmap layer whose tile is ready
+    pub show_voids: u32,
+    pub stop_count: u32,//colour ramp stops in the stop buffer
+    pub normalised: u32,
+    pub stepped: u32,
+    _padding: [u32; 2],//water lands on a 16 byte boundary like the vec4f in wgsl
+    pub water: [f32; 4],//rgb and 1 when the ramp has a water colour
+}
+pub struct Terrain {//Public Terrain struct
+    pub moves: [f32; 2],//moving by the keyboard input, full resolution samples from the north west corner of the current tile
+    pub level_of_detail: u32,//varrying level of detail higher level_of_detail larger increments of rendering lower render quality
+    pub water_level: f32,//metres, anything lower is drawn as water
+    pub colour_height_max: f32,//metres at the top of the colour bands
+    pub ramps: Vec<ColorRamp>,//colour ramps that can be switched between
+    pub ramp: usize,//the one in use
+    pub lod_steps: Vec<u32>,//samples between vertices at each level of detail
+    pub vertical_exaggeration: f32,//only applied in the model matrix so heights stay in metres
+    //latitude and longitude of the south west corner of the current srtm tile, south and west are negative
+    pub lat :i32,
+    pub long :i32,
+    pub chunksize:u32,//vertices along each side of a chunk's full detail grid
+    pub samples_per_degree: u32,//full resolution of the terrain, taken from the starting tile
+    tiles: TileCache,//srtm tiles loaded in the background around the current tile
+    measures: HashMap<Node, ChunkMeasure>,//quadtree nodes measured so far
+    measured: Option<(i32, i32, u32, u32)>,//lat, long, resolution and vertex spacing the measures are for
+    pub interpolation: Interpolation,//used by height_at between srtm samples
+    pub minimised: bool,
+    max_resolution: u32,//most samples per degree the gpu's heightmap textures can hold
+    pub show_voids: bool,//highlight heights that were filled in where the srtm data had voids
 }
-impl Defaultable for Threaded {
-    fn default_with_params(lat:u32,long:u32,minimised:bool) -> Self {//Calculating srtm tile based on lat and long and outputting in a vector of vectors
-        let (tx, rx) = mpsc::channel();
-        let thread = thread::spawn(move||{
-            let mut map :Vec<Vec<f32>> = vec![];
-            let mut height_min = f32::MAX;
-            let mut height_max = f32::MIN;
-            let worldmap: Tile = Tile::from_file("src/Scotlandhgt/N".to_owned() + &*lat.to_string() +"W00"+ &*long.to_string() +".hgt").unwrap_or(Tile::from_file("src/Scotlandhgt/N00W000.hgt").unwrap());
-            let mut total = 3600;
-            if minimised == true{
-                total /=4
-            }
-            for x in 0..total{
-                let mut p1:Vec<f32> = vec![];
-                for z in 0..total{
-                    let y =  Tile::get(&worldmap, x as u32, z as u32) as f32;
-                    height_min = if y  < height_min { y } else { height_min };
-                    height_max = if y  > height_max { y } else { height_max };
-                    p1.push(y);
 
-                }
-                map.push(p1);
-            }
+impl Terrain {
+    pub fn new(settings: &Settings, source: Arc<dyn ElevationSource>, max_resolution: u32) -> Self {
+        //tiles at other resolutions are resampled to match the starting one so the sample grid stays regular
+        let centre = settings.start;
+        let (lat, long) = (centre.lat.floor() as i32, wrap_long(centre.long.floor() as i32));
+        let samples_per_degree = source.samples_per_degree(lat, long).unwrap_or(DEFAULT_SAMPLES_PER_DEGREE);
+        let ramps = color_ramp::ramps(settings);
+        let ramp = ramps.iter().position(|r| r.name() == settings.ramp).unwrap_or(0);
+        let mut terrain = Self {
+            moves: [0.0, 0.0],
+            level_of_detail: 0,//full detail until the buffers are sized, State applies settings.level_of_detail after
+            water_level: settings.water_level,
+            colour_height_max: settings.colour_height_max,
+            ramps,
+            ramp,
+            lod_steps: settings.lod_steps.clone(),
+            vertical_exaggeration: settings.vertical_exaggeration,
+            chunksize: settings.chunksize,
+            samples_per_degree,
+            lat,
+            long,
+            tiles: TileCache::new(source, settings.void_fill, TILE_WORKERS, TILE_CACHE_CAPACITY),
+            measures: HashMap::new(),
+            measured: None,
+            interpolation: settings.interpolation,
+            minimised: false,
+            max_resolution,
+            show_voids: false,
+        };
+        terrain.place_at(centre);
+        terrain
+    }
 
-            for x in 0..total as usize {//normalisation on the entie srtm tile
-                for z in 0..total as usize {
-                    map[x][z] = (map[x][z] as f32 )/(height_max - height_min);
-                }
+    pub fn place_at(&mut self, centre: GeoPoint) {
+        //centre the terrain on a latitude and longitude, the render origin ends up there
+        self.lat = centre.lat.floor() as i32;
+        self.long = wrap_long(centre.long.floor() as i32);
+        self.moves = [0.0, 0.0];
+        let (sx, sz) = self.frame().geo_to_sample(centre.lat, centre.long);
+        self.moves = [sx.round() as f32, sz.round() as f32];//whole samples like moving with the keys
+        self.recentre();
+        self.request_neighbourhood();
+    }
+
+    pub fn frame(&self) -> Frame {
+        //how render space lines up with the earth for the current position
+        Frame {
+            lat: self.lat,
+            long: self.long,
+            moves: self.moves,
+            samples_per_degree: self.samples_per_degree,
+            resolution: self.resolution(),
+            vertical_scale: self.vertical_scale(),
+        }
+    }
+
+    pub fn resolution(&self) -> u32 {
+        //samples per degree being rendered, minimised tiles keep every fourth sample
+        //tiles finer than the gpu can hold, like 1/3 arc second dems, are resampled down to fit
+        let resolution = if self.minimised { (self.samples_per_degree / 4).max(1) } else { self.samples_per_degree };
+        resolution.min(self.max_resolution)
+    }
+
+    fn request_neighbourhood(&mut self) {
+        //queue the 3x3 tiles around the current tile at the resolution being rendered
+        let resolution = self.resolution();
+        for dlat in -1..=1 {
+            for dlong in -1..=1 {
+                self.tiles.request(TileKey { lat: self.lat + dlat, long: wrap_long(self.long + dlong), resolution });
             }
-            tx.send(map).unwrap();//Send data to the receiver
-        });
-        Self {
-            refer: rx,
-            thread: thread,
         }
     }
-}
 
-impl Threaded {
-    fn transferwithret(&mut self, lat:u32,long:u32,min:bool){
-        //Transfering ownership from an old thread to a new thread clears memory
-        let transfer = Threaded::default_with_params(lat,long,min);
-        self.thread=transfer.thread;
-        self.refer=transfer.refer;
+    pub fn poll_tiles(&mut self) -> bool {
+        //true when new tiles arrived and the terrain should be rebuilt
+        let arrived = self.tiles.poll();
+        for key in &arrived {
+            self.forget_measures(*key);
+        }
+        !arrived.is_empty()
     }
-}
 
+    fn forget_measures(&mut self, key: TileKey) {
+        //only the nodes over a tile that arrived measured it as sea level, the rest stay measured
+        if key.resolution != self.resolution() {
+            return;
+        }
+        let total = key.resolution as i64;
+        let x0 = wrap_long(key.long - self.long) as i64 * total;
+        let z0 = (self.lat - key.lat) as i64 * total;
+        let chunk_span = self.chunksize - 1;
+        //a sample on the shared edge can come from either tile, so the edges count as overlapping
+        self.measures.retain(|node, _| {
+            let span = node.span(chunk_span);
+            node.x > x0 + total || node.x + span < x0 || node.z > z0 + total || node.z + span < z0
+        });
+    }
 
-pub struct Terrain {//Public Terrain struct
-    pub offsets: [f32; 2],//Chunk offsets
-    pub moves: [f32; 2],//moving by the keyboard input
-    pub level_of_detail: u32,//varrying level of detail higher level_of_detail larger increments of rendering lower render quality
-    pub water_level: f32,
-    //srtm tile adapted to vectors of vectors
-    mapdata: Vec<Vec<f32>>,
-    //loading new srtm tiles
-    mapdatanextx: Vec<Vec<f32>>,
-    mapdatanextz: Vec<Vec<f32>>,
-    mapdatanextxz: Vec<Vec<f32>>,
-    minmapdata: Vec<Vec<f32>>,
-    minmapdatanextx: Vec<Vec<f32>>,
-    minmapdatanextz: Vec<Vec<f32>>,
-    minmapdatanextxz: Vec<Vec<f32>>,
-    pub doneinit :u32,
-    pub mindoneinit :u32,
-    doneinitx :u32,
-    doneinitz :u32,
-    doneinitxz: u32,
-    donexe :u32,
-    donexw :u32,
-    donezn :u32,
-    donezs :u32,
-    donese :u32,
-    donesw :u32,
-    donene :u32,
-    donenw :u32,
-    mindoneinitx :u32,
-    mindoneinitz :u32,
-    mindoneinitxz: u32,
-    mindonexe :u32,
-    mindonexw :u32,
-    mindonezn :u32,
-    mindonezs :u32,
-    mindonese :u32,
-    mindonesw :u32,
-    mindonene :u32,
-    mindonenw :u32,
-    north: bool,
-    east: bool,
-    south: bool,
-    west: bool,
-    //latitude and longitude coordinates
-    pub lat :u32,
-    pub long :u32,
-    pub chunksize:u32,
-    //threads used
-    initthread: Threaded,
-    nthread: Threaded,
-    ethread: Threaded,
-    sthread: Threaded,
-    wthread: Threaded,
-    nethread: Threaded,
-    esthread: Threaded,
-    swthread: Threaded,
-    wnthread: Threaded,
-    mininitthread: Threaded,
-    minnthread: Threaded,
-    minethread: Threaded,
-    minsthread: Threaded,
-    minwthread: Threaded,
-    minnethread: Threaded,
-    minesthread: Threaded,
-    minswthread: Threaded,
-    minwnthread: Threaded,
-    pub minimised: bool
+    pub fn tiles_loading(&self) -> bool {
+        self.tiles.pending() > 0
+    }
 
-}
+    pub fn vertical_scale(&self) -> f32 {
+        //render units per metre of height, one render unit is one north south sample at the resolution being rendered
+        let metres_per_sample = (METRES_PER_DEGREE / self.resolution() as f64) as f32;
+        self.vertical_exaggeration / metres_per_sample
+    }
 
-impl Default for Terrain {
-        fn default() -> Self {
-            let lat =55;
-            let long = 5;
-            //initialise all threads at runtime correspondng to the correct layout
-            let mininitialthread = Threaded::default_with_params(lat,long,true);
-            let minnorththread = Threaded::default_with_params(lat+1,long,true);
-            let minnortheastthread = Threaded::default_with_params(lat+1,long-1,true);
-            let mineastthread = Threaded::default_with_params(lat,long-1,true);
-            let mineastsouththread = Threaded::default_with_params(lat-1,long-1,true);
-            let minsouththread = Threaded::default_with_params(lat-1,long,true);
-            let minsouthwestthread = Threaded::default_with_params(lat-1,long+1,true);
-            let minwestthread = Threaded::default_with_params(lat,long+1,true);
-            let minwestnorththread = Threaded::default_with_params(lat+1,long+1,true);
-            let initialthread = Threaded::default_with_params(lat,long,false);
-            let norththread = Threaded::default_with_params(lat+1,long,false);
-            let northeastthread = Threaded::default_with_params(lat+1,long-1,false);
-            let eastthread = Threaded::default_with_params(lat,long-1,false);
-            let eastsouththread = Threaded::default_with_params(lat-1,long-1,false);
-            let souththread = Threaded::default_with_params(lat-1,long,false);
-            let southwestthread = Threaded::default_with_params(lat-1,long+1,false);
-            let westthread = Threaded::default_with_params(lat,long+1,false);
-            let westnorththread = Threaded::default_with_params(lat+1,long+1,false);
-        Self {
-            offsets: [0.0, 0.0],
-            moves:[1800.0,1800.0],//start in the middle of the srtm tile
-            level_of_detail: 0,
-            water_level: 0.001,
-            mapdata: vec![],
-            mapdatanextx: vec![],
-            mapdatanextz: vec![],
-            mapdatanextxz: vec![],
-            minmapdata: vec![],
-            minmapdatanextx: vec![],
-            minmapdatanextz: vec![],
-            minmapdatanextxz: vec![],
-            doneinit:0,
-            mindoneinit:0,
-            doneinitx :0,
-            doneinitz :0,
-            doneinitxz :0,
-            donexe:0,
-            donexw:0,
-            donezn:0,
-            donezs:0,
-            donese:0,
-            donesw:0,
-            donene:0,
-            donenw:0,
-            mindoneinitx :0,
-            mindoneinitz :0,
-            mindoneinitxz :0,
-            mindonexe:0,
-            mindonexw:0,
-            mindonezn:0,
-            mindonezs:0,
-            mindonese:0,
-            mindonesw:0,
-            mindonene:0,
-            mindonenw:0,
-            north: false,
-            east: false,
-            south: false,
-            west: false,
-            chunksize:241,
-            lat:lat,
-            long:long,
-            initthread: initialthread,
-            nthread: norththread,
-            ethread: eastthread,
-            sthread: souththread,
-            wthread: westthread,
-            nethread: northeastthread,
-            esthread: eastsouththread,
-            swthread: southwestthread,
-            wnthread: westnorththread,
-            mininitthread: mininitialthread,
-            minnthread: minnorththread,
-            minethread: mineastthread,
-            minsthread: minsouththread,
-            minwthread: minwestthread,
-            minnethread: minnortheastthread,
-            minesthread: mineastsouththread,
-            minswthread: minsouthwestthread,
-            minwnthread: minwestnorththread,
-            minimised: false,
-        }
+    pub fn height_at(&mut self, lat: f64, long: f64) -> Option<f32> {
+        //full resolution height anywhere, None while the tiles needed are still loading
+        HeightSampler::new(&mut self.tiles, self.samples_per_degree).height_at(lat, long, self.interpolation)
     }
-}
 
-impl Terrain {
+    fn recentre(&mut self) {
+        //once the plane leaves the current tile the neighbouring tile becomes the current one
+        let total = self.samples_per_degree as f32;
+        let mut moved = false;
+        while self.moves[0] >= total {
+            self.moves[0] -= total;
+            self.long = wrap_long(self.long + 1);
+            moved = true;
+        }
+        while self.moves[0] < 0.0 {
+            self.moves[0] += total;
+            self.long = wrap_long(self.long - 1);
+            moved = true;
+        }
+        while self.moves[1] >= total {
+            self.moves[1] -= total;
+            self.lat -= 1;
+            moved = true;
+        }
+        while self.moves[1] < 0.0 {
+            self.moves[1] += total;
+            self.lat += 1;
+            moved = true;
+        }
+        if moved {
+            println!("chunk");
+        }
+    }
 
-    pub fn create_indices(&mut self, width: u32, height: u32) -> (Vec<u32>, Vec<u32>) {
+    pub fn create_indices(&self, width: u32, height: u32) -> (Vec<u32>, Vec<u32>) {
         //creating the indices based on the height and width of each chunk
         let n_vertices_per_row = height;
         let mut indices:Vec<u32> = vec![];
@@ -247,590 +252,161 @@ impl Terrain {
         }
         (indices, texindices)
     }
-    //Adapting srtm tile to vector of vectors moved in theads
-    /*pub fn find_world_map(&mut self) {
-        let mut height_min = f32::MAX;
-        let mut height_max = f32::MIN;
-        let worldmap: Tile =  Tile::from_file("src/Scotlandhgt/N".to_owned() + &*self.lat.to_string() +"W00"+ &*self.long.to_string() +".hgt").unwrap_or(Tile::from_file("src/Scotlandhgt/N00W000.hgt").unwrap());
-
-        for x in 0..3600 {
-            let mut p1:Vec<f32> = vec![];
-            for z in 0..3600 {
-                let y =  Tile::get(&worldmap, x as u32, z as u32) as f32;
-                height_min = if y < height_min { y } else { height_min };
-                height_max = if y > height_max { y } else { height_max };
-                p1.push(y);
-            }
-            self.mapdata.push(p1);
-        }
-
-        for x in 0..3600 as usize {
-            for z in 0..3600 as usize {
-                self.mapdata[x][z] = (self.mapdata[x][z])/(height_max - height_min);
-            }
-        }
-    }*/
-    fn color_interp(&mut self, color:&Vec<[f32;3]>, ta:&Vec<f32>, t:f32) -> [f32;3] {
-        //interpreting color based on the y value
-        let len = 6usize;
-        let mut res = [0f32;3];
-        for i in 0..len - 1 {
-            if t >= ta[i] && t < ta[i + 1] {
-                res = color[i];
-            }
-        }
-        if t == ta[len-1] {
-            res = color[len-2];
-        }
-        res
+    pub fn increment_count(&self) -> u32 {
+        //samples between vertices at the current level of detail
+        self.lod_steps[(self.level_of_detail as usize).min(self.lod_steps.len() - 1)]
     }
 
-    fn add_terrain_colors(&mut self, color:&Vec<[f32;3]>, ta:&Vec<f32>, tmin:f32, tmax:f32, t:f32) -> [f32;3] {
-        //adding the terrain color
-        let mut tt = if t < tmin { tmin } else if t > tmax { tmax } else { t };
-        tt = (tt - tmin)/(tmax - tmin);
-        let t1 = self.shift_water_level(ta);
-        self.color_interp(color, &t1, tt)
-    }
-    fn shift_water_level(&mut self, ta:&Vec<f32>) -> Vec<f32> {
-        //adding the water level
-        let mut t1 = vec![0f32; 6];
-        let r = (1.0 - self.water_level)/(1.0 - ta[1]);
-        t1[1] = self.water_level;
-        for i in 1..5usize {
-            let del = ta[i+1] - ta[i];
-            t1[i+1] = t1[i] + r * del;
-        }
-        t1
+    pub fn next_ramp(&mut self) -> &str {
+        //switch to the next colour ramp, the terrain has to be rebuilt to use it
+        self.ramp = (self.ramp + 1) % self.ramps.len();
+        self.ramps[self.ramp].name()
     }
 
 
 
-    pub fn create_terrain_data(&mut self) -> (Vec<Vertex>,Vec<Vertex>, u32) {
-        //level of detail used to calculate the increment count
-        let increment_count = if self.level_of_detail <= 5 { self.level_of_detail + 1} else { 2*(self.level_of_detail - 2)};
-        let vertices_per_row = (self.chunksize - 1)/increment_count + 1;
-        //colormap
-        let cdata =  vec![
-            [0.055f32, 0.529, 0.8],
-            [0.761, 0.698, 0.502],
-            [0.204, 0.549, 0.192],
-            [0.353, 0.302, 0.255],
-            [1.0, 0.98, 0.98]
-        ];
-        let tdata = vec![[1f32, 1.0, 1.0]; 5];
-        let ta = vec![0.0f32, 0.3, 0.35, 0.7, 0.9, 1.0];
-        //receive the initial srtm tile from the thread at runtime only once
-        if self.minimised == false{
-        if self.doneinit == 0 {
-            for received in &self.initthread.refer {
-                self.mapdata = received;
-                self.doneinit+=1;
-            }
-        }}else{if self.mindoneinit == 0 {
-            for received in &self.mininitthread.refer {
-                self.minmapdata = received;
-                self.mindoneinit+=1;
+    pub fn create_grids(&self) -> Grids {
+        //a chunk's grid at every level of detail, every chunk draws from the same buffers
+        let mut grids = Grids { vertices: vec![], indices: vec![], lines: vec![], levels: vec![] };
+        for &step in &self.lod_steps {
+            let base = grids.vertices.len() as u32;
+            let n = (self.chunksize - 1) / step + 1;
+            for x in (0..self.chunksize).step_by(step as usize) {
+                for z in (0..self.chunksize).step_by(step as usize) {
+                    grids.vertices.push(Vertex { position: [x, z], skirt: 0 });
+                }
             }
-        }
-
-        }
-
-
-        let mut data:Vec<Vertex> = vec![];
-        let mut texturedata:Vec<Vertex> = vec![];
-        if self.minimised == false {
-            for x in (0..self.chunksize as usize).step_by(increment_count as usize) {
-                for z in (0..self.chunksize as usize).step_by(increment_count as usize) {
-                    //calculating new x and z values based on the chunk and the how much the user moves
-                    let usex: i32 = (x as f32 + self.offsets[0] + self.moves[0]) as i32;
-                    let usez: i32 = (z as f32 + self.offsets[1] + self.moves[1]) as i32;
-                    let mut y = 0.0;
-                    match usez as i32 {//retieving other srtm data from tiles depending on dirction making sure its only done once
-                        m if m < -1800 => {
-                            println!("chunk");
-                            self.moves[1] += 3600.0;
-                            self.mapdata = vec![];
-                            self.mapdata = self.mapdatanextz.clone();
-                            self.lat += 1;
-                            self.doneinitxz = 2;
-                            self.doneinitz = 2;
-                            self.doneinitx = 2;
-                            self.donezn = 0;
-                            self.donene = 0;
-                            self.donenw = 0;
-                            self.north = true;
-                        }
-                        -1800..=-1 => {
-                            self.north = true;
-                        }
-                        0..=200 => {
-                            if self.donezn == 0 {
-                                for received in &self.nthread.refer {
-                                    self.mapdatanextz = vec![];
-                                    self.mapdatanextz = received
-                                }
-
-                                self.donezn += 1;
-                            }
-                        }
-                        201..=3400 => {
-                            self.doneinitxz = 0;
-                            if self.doneinitz == 2 {//new srtm tiles to be created
-                                Threaded::transferwithret(&mut self.sthread, self.lat - 1, self.long, false);
-                                Threaded::transferwithret(&mut self.nthread, self.lat + 1, self.long, false);
-
-                                self.doneinitz += 1;
-                            }
-                            if self.doneinitxz == 2 {
-                                Threaded::transferwithret(&mut self.nethread, self.lat + 1, self.long - 1, false);
-                                Threaded::transferwithret(&mut self.wnthread, self.lat + 1, self.long + 1, false);
-                                Threaded::transferwithret(&mut self.esthread, self.lat - 1, self.long - 1, false);
-                                Threaded::transferwithret(&mut self.swthread, self.lat - 1, self.long + 1, false);
-
-                                self.doneinitz += 1;
-                            }
-                        }
-                        3401..=3599 => {
-                            if self.donezs == 0 {
-                                for received in &self.sthread.refer {
-                                    self.mapdatanextz = vec![];
-                                    self.mapdatanextz = received
-                                }
-                                self.donezs += 1;
-                            }
-                        }
-                        3600..=5400 => {
-                            self.south = true;
-                        }
-                        _ => {
-                            println!("chunk");
-                            self.moves[1] -= 3600.0;
-                            self.mapdata = vec![];
-                            self.mapdata = self.mapdatanextz.clone();
-                            self.lat -= 1;
-                            self.doneinitxz = 2;
-                            self.doneinitz = 2;
-                            self.doneinitx = 2;
-                            self.donezs = 0;
-                            self.donesw = 0;
-                            self.donese = 0;
-                            self.south = true;
-                        }
-                    }
-                    match usex as i32 {
-                        n if n < -1800 => {
-                            println!("chunk");
-                            self.moves[0] += 3600.0;
-                            self.mapdata = vec![];
-                            self.mapdata = self.mapdatanextx.clone();
-                            self.long += 1;
-                            self.doneinitxz = 2;
-                            self.doneinitz = 2;
-                            self.doneinitx = 2;
-                            self.donexw = 0;
-                            self.donesw = 0;
-                            self.donenw = 0;
-                            self.west = true
-                        }
-                        -1800..=-1 => {
-                            self.west = true;
-                        }
-                        0..=200 => {
-                            if self.donexw == 0 {
-                                for received in &self.wthread.refer {
-                                    self.mapdatanextx = vec![];
-                                    self.mapdatanextx = received
-                                }
-
-                                self.donexw += 1;
-                            }
-                        }
-                        201..=3400 => {
-                            self.doneinitxz = 0;
-                            if self.doneinitx == 2 {
-                                Threaded::transferwithret(&mut self.ethread, self.lat, self.long - 1, false);
-
-                                Threaded::transferwithret(&mut self.wthread, self.lat, self.long + 1, false);
-
-                                self.doneinitx += 1;
-                            }
-                            if self.doneinitxz == 2 {
-                                Threaded::transferwithret(&mut self.nethread, self.lat + 1, self.long - 1, false);
-                                Threaded::transferwithret(&mut self.wnthread, self.lat + 1, self.long + 1, false);
-                                Threaded::transferwithret(&mut self.esthread, self.lat - 1, self.long - 1, false);
-                                Threaded::transferwithret(&mut self.swthread, self.lat - 1, self.long + 1, false);
-
-                                self.doneinitz += 1;
-                            }
-                        }
-                        3401..=3599 => {
-                            if self.donexe == 0 {
-                                for received in &self.ethread.refer {
-                                    self.mapdatanextx = vec![];
-                                    self.mapdatanextx = received
-                                }
-                                self.donexe += 1;
-                            }
-                        }
-                        3600..=5400 => {
-                            self.east = true;
-                        }
-                        _ => {
-                            println!("chunk");
-                            self.moves[0] -= 3600.0;
-                            self.mapdata = vec![];
-                            self.mapdata = self.mapdatanextx.clone();
-                            if self.long > 1 {
-                                self.long -= 1;
-                            }
-                            self.doneinitxz = 2;
-                            self.doneinitz = 2;
-                            self.doneinitx = 2;
-                            self.donexe = 0;
-                            self.donene = 0;
-                            self.donese = 0;
-                            self.east = true;
-                        }
-                    }
-                    //finding the correct map data to use based on the direction moving in
-                    if self.south || self.north || self.east || self.west {
-                    if self.south {
-                        if self.east{if self.donese ==0 {
-                            for received in &self.esthread.refer {
-                                self.mapdatanextxz = vec![];
-                                self.mapdatanextxz = received
-                            }
-
-                            self.donese +=1;
-                        }
-                            y = self.mapdatanextxz[(usex -3600) as usize][(usez - 3600) as usize];
-                        }else if self.west{
-                            if self.donesw ==0 {
-                            for received in &self.swthread.refer {
-                                self.mapdatanextxz = vec![];
-                                self.mapdatanextxz = received
-                            }
-
-                            self.donesw +=1;
-                        }
-                            y = self.mapdatanextxz[(usex +3600) as usize][(usez - 3600) as usize];
-                        }else{
-                            y = self.mapdatanextz[usex as usize][(usez - 3600) as usize];
-                        }
-                    }else{
-                    if self.north {
-                        if self.east{
-                            if self.donene ==0 {
-                            for received in &self.nethread.refer {
-                                self.mapdatanextxz = vec![];
-                                self.mapdatanextxz = received
-                            }
-
-                            self.donene +=1;
-                        }
-                            y = self.mapdatanextxz[(usex - 3600) as usize][(usez + 3600) as usize];
-                        }else if self.west{
-                            if self.donenw ==0 {
-                            for received in &self.wnthread.refer {
-                                self.mapdatanextxz = vec![];
-                                self.mapdatanextxz = received
-                            }
-
-                            self.donenw +=1;
-                        }
-                            y = self.mapdatanextxz[(usex +3600) as usize][(usez + 3600) as usize];
-                        }else{
-                            y = self.mapdatanextz[usex as usize][(usez + 3600) as usize];
-                        }
-                    }else{
-                    if self.east{
-                        y = self.mapdatanextx[(usex - 3600) as usize][usez as usize];
-                    }else{
-                    if self.west{
-                        y = self.mapdatanextx[(usex + 3600) as usize][usez as usize];
-                    }}}}
-                        }else{
-                    y = self.mapdata[usex as usize][usez as usize];
+            let (indices, lines) = self.create_indices(n, n);
+            let (first_index, first_line) = (grids.indices.len() as u32, grids.lines.len() as u32);
+            grids.indices.extend(indices.iter().map(|i| i + base));
+            grids.lines.extend(lines.iter().map(|i| i + base));
+            if n > 1 {
+                //walk round the edge, each edge vertex gets a copy underneath joined to it by a strip of triangles
+                let north = 0..n;
+                let east = (1..n).map(|a| a * n + n - 1);
+                let south = (0..n - 1).rev().map(|b| (n - 1) * n + b);
+                let west = (1..n - 1).rev().map(|a| a * n);
+                let edge: Vec<u32> = north.chain(east).chain(south).chain(west).collect();
+                let skirt_base = grids.vertices.len() as u32;
+                for &i in &edge {
+                    let position = grids.vertices[(base + i) as usize].position;
+                    grids.vertices.push(Vertex { position, skirt: 1 });
                 }
-                    self.north = false;
-                    self.east = false;
-                    self.south = false;
-                    self.west = false;
-                    //print!("{} ,,",y);
-                    if y < self.water_level {//making sure the y values to be water values are the same for a smooth water line
-                        y = self.water_level - 0.01;
-                    }
-                    let position = [x as f32, y, z as f32];
-                    let color = self.add_terrain_colors(&cdata, &ta, 0.0, 1.0, y);
-                    let texturecolor = self.add_terrain_colors(&tdata, &ta, 0.0, 1.0, y);
-                    data.push(Vertex { position, color });
-                    texturedata.push(Vertex { position, color: texturecolor });
+                for k in 0..edge.len() {
+                    let next = (k + 1) % edge.len();
+                    let (top, top_next) = (base + edge[k], base + edge[next]);
+                    let (bottom, bottom_next) = (skirt_base + k as u32, skirt_base + next as u32);
+                    grids.indices.extend([top, top_next, bottom_next, bottom_next, bottom, top]);
                 }
             }
-        }else{
-            for x in 0..(self.chunksize) as usize {
-            for z in 0..(self.chunksize) as usize {
-                //calculating new x and z values based on the chunk and the how much the user moves
-                let usex : i32= (x as f32 + (self.offsets[0]) + (self.moves[0])/4.0)as i32;
-                let usez : i32= (z as f32 + (self.offsets[1]) + (self.moves[1])/4.0)as i32;
-                let mut y = 0.0;
-                match usez as i32 {//retieving other srtm data from tiles depending on dirction making sure its only done once
-                    m if m < -450 =>{
-                        println!("chunk");
-                            self.moves[1] += 3600.0;
-                            self.minmapdata = vec![];
-                            self.minmapdata = self.minmapdatanextz.clone();
-                            self.lat += 1;
-                            self.mindoneinitxz =2;
-                            self.mindoneinitz =2;
-                            self.mindoneinitx=2;
-                            self.mindonezn = 0;
-                            self.mindonene = 0;
-                            self.mindonenw = 0;
-                            self.north= true;
-                    }
-                    -450 ..=-1 => {
-                        self.north = true;
-
-                    }
-                     0 ..=50 => {
-                        if self.mindonezn==0 {
-                            for received in &self.minnthread.refer {
-                                self.minmapdatanextz = vec![];
-                                self.minmapdatanextz = received
-                            }
-
-                            self.mindonezn += 1;
-                        }
-                    }
-                    51 ..=850 => {
-                        self.mindoneinitxz = 0;
-                        if self.mindoneinitz ==2 {//new srtm tiles to be created
-                                Threaded::transferwithret(&mut self.minsthread,self.lat-1,self.long,true);
-                                Threaded::transferwithret(&mut self.minnthread,self.lat+1,self.long,true);
-
-                            self.mindoneinitz +=1;
-                        }
-                        if self.mindoneinitxz ==2 {
-                                Threaded::transferwithret(&mut self.minnethread,self.lat+1,self.long-1,true);
-                                Threaded::transferwithret(&mut self.minwnthread,self.lat+1,self.long+1,true);
-                                Threaded::transferwithret(&mut self.minesthread,self.lat-1,self.long-1,true);
-                                Threaded::transferwithret(&mut self.minswthread,self.lat-1,self.long+1,true);
-
-                            self.mindoneinitz +=1;
-                        }
-                    }
-                    851 ..= 899=>{
-                        if self.mindonezs == 0 {
-                            for received in &self.minsthread.refer {
-                                self.mapdatanextz = vec![];
-                                self.mapdatanextz = received
-                            }
-                            self.donezs += 1;
-                        }
-                    }
-                    900 ..= 1350=>{
-                        self.south= true;
-                    }
-                    _ => {
-                        println!("chunk");
-                        self.moves[1] -=3600.0;
-                        self.minmapdata = vec![];
-                        self.minmapdata = self.minmapdatanextz.clone();
-                        self.lat -= 1;
-                        self.mindoneinitxz =2;
-                        self.mindoneinitz =2;
-                        self.mindoneinitx=2;
-                        self.mindonezs=0;
-                        self.mindonesw =0;
-                        self.mindonese = 0;
-                        self.south= true;
-
-                }}
-                match usex as i32 {
-                    n if n < -450 =>{
-                        println!("chunk");
-                        self.moves[0] += 3600.0;
-                        self.minmapdata = vec![];
-                        self.minmapdata = self.minmapdatanextx.clone();
-                        self.long +=1;
-                        self.mindoneinitxz =2;
-                        self.mindoneinitz =2;
-                        self.mindoneinitx=2;
-                        self.mindonexw=0;
-                        self.mindonesw =0;
-                        self.mindonenw = 0;
-                        self.west = true
-                    }
-                    -450 ..=-1 => {
-                        if self.mindonexw==0 {
-                            for received in &self.minwthread.refer {
-                                self.minmapdatanextx = vec![];
-                                self.minmapdatanextx = received
-                            }
-
-                            self.mindonexw += 1;
-                        }
-                        self.west = true;
-                    }
-                     0 ..= 50 => {
-                         self.west = false;
-                        if self.mindonexw==0 {
-                            for received in &self.minwthread.refer {
-                                self.minmapdatanextx = vec![];
-                                self.minmapdatanextx = received
-                            }
-
-                            self.mindonexw += 1;
-                        }
-                    }
-                    51 ..=850 =>{
-                        self.mindoneinitxz = 0;
-                        if self.mindoneinitx ==2 {
-                            Threaded::transferwithret(&mut self.minethread,self.lat,self.long-1,true);
-
-                            Threaded::transferwithret(&mut self.minwthread,self.lat,self.long+1,true);
-
-                            self.mindoneinitx +=1;
-                        }
-                        if self.mindoneinitxz ==2 {
-                                Threaded::transferwithret(&mut self.minnethread,self.lat+1,self.long-1,true);
-                                Threaded::transferwithret(&mut self.minwnthread,self.lat+1,self.long+1,true);
-                                Threaded::transferwithret(&mut self.minesthread,self.lat-1,self.long-1,true);
-                                Threaded::transferwithret(&mut self.swthread,self.lat-1,self.long+1,true);
-
-                            self.mindoneinitz +=1;
-                        }
-                    }
-                    851 ..= 899=>{
-                        if self.mindonexe == 0 {
-                            for received in &self.minethread.refer {
-                                self.minmapdatanextx = vec![];
-                                self.minmapdatanextx = received
-                            }
-                            self.mindonexe += 1;
-                        }
-                    }
-                    900 ..= 1350=>{
-                        self.east =true;
-                    }
-                    _ => {
-                        println!("chunk");
-                        self.moves[0] -=3600.0;
-                        self.minmapdata = vec![];
-                        self.minmapdata = self.minmapdatanextx.clone();
-                        if self.long >1 {
-                            self.long -= 1;
-                        }
-                        self.mindoneinitxz =2;
-                        self.mindoneinitz =2;
-                        self.mindoneinitx=2;
-                        self.mindonexe=0;
-                        self.mindonene = 0;
-                        self.mindonese = 0;
-                        self.east =true;
-
-                }}
-                //finding the correct map data to use based on the direction moving in
-                if self.south || self.north || self.east || self.west {
-                    if self.south {
-                        if self.east{if self.mindonese ==0 {
-                            for received in &self.minesthread.refer {
-                                self.minmapdatanextxz = vec![];
-                                self.minmapdatanextxz = received
-                            }
-
-                            self.mindonese +=1;
-                        }
-                            y = self.minmapdatanextxz[(usex -900) as usize][(usez - 900) as usize];
-                        }else if self.west{
-                            if self.mindonesw ==0 {
-                            for received in &self.minswthread.refer {
-                                self.minmapdatanextxz = vec![];
-                                self.minmapdatanextxz = received
-                            }
-
-                            self.mindonesw +=1;
-                        }
-                            y = self.minmapdatanextxz[(usex +900) as usize][(usez - 900) as usize];
-                        }else{
-                            y = self.minmapdatanextz[usex as usize][(usez - 900) as usize];
-                        }
-                    }else{
-                    if self.north {
-                        if self.east{
-                            if self.mindonene ==0 {
-                            for received in &self.minnethread.refer {
-                                self.minmapdatanextxz = vec![];
-                                self.minmapdatanextxz = received
-                            }
+            grids.levels.push(GridLevel {
+                indices: first_index..grids.indices.len() as u32,
+                lines: first_line..grids.lines.len() as u32,
+            });
+        }
+        grids
+    }
 
-                            self.mindonene +=1;
-                        }
-                            y = self.minmapdatanextxz[(usex - 900) as usize][(usez + 900) as usize];
-                        }else if self.west{
-                            if self.mindonenw ==0 {
-                            for received in &self.minwnthread.refer {
-                                self.minmapdatanextxz = vec![];
-                                self.minmapdatanextxz = received
-                            }
+    pub fn measure(&mut self, node: Node) -> ChunkMeasure {
+        //height range and error of a quadtree node, kept until the terrain under it changes
+        let key = (self.lat, self.long, self.resolution(), self.increment_count());
+        if self.measured != Some(key) {
+            self.measures.clear();
+            self.measured = Some(key);
+        }
+        if let Some(measure) = self.measures.get(&node) {
+            return *measure;
+        }
+        if self.measures.len() >= MEASURE_CACHE_CAPACITY {
+            self.measures.clear();
+        }
+        let measure = self.measure_node(node);
+        self.measures.insert(node, measure);
+        measure
+    }
 
-                            self.mindonenw +=1;
-                        }
-                            y = self.minmapdatanextxz[(usex +900) as usize][(usez + 900) as usize];
-                        }else{
-                            y = self.minmapdatanextz[usex as usize][(usez + 900) as usize];
-                        }
-                    }else{
-                    if self.east{
-                        y = self.minmapdatanextx[(usex - 900) as usize][usez as usize];
-                    }else{
-                    if self.west{
-                        y = self.minmapdatanextx[(usex + 900) as usize][usez as usize];
-                    }}}}
-                }else{
-                    y = self.minmapdata[usex as usize][usez as usize];
-                }
-                self.north = false;
-                self.east = false;
-                self.south = false;
-                self.west = false;
-               if y < self.water_level {//making sure the y values to be water values are the same for a smooth water line
-                    y = self.water_level - 0.01;
-                }
-                let position = [x as f32, y, z as f32];
-                let color = self.add_terrain_colors(&cdata, &ta, 0.0, 1.0, y);
-                let texturecolor = self.add_terrain_colors(&tdata, &ta, 0.0, 1.0, y);
-                data.push(Vertex { position, color});
-                texturedata.push(Vertex { position, color: texturecolor });
+    fn measure_node(&mut self, node: Node) -> ChunkMeasure {
+        //samples at the spacing of the node's children, or full detail for level 0, against the node's own coarser grid
+        let increment = self.increment_count() as i64;
+        let fine = if node.level == 0 { 1 } else { increment << (node.level - 1) };
+        let step = ((increment << node.level) / fine) as usize;//fine samples between the node's vertices
+        let n = ((self.chunksize as i64 - 1) / increment) as usize * step + 1;
+        let resolution = self.resolution();
+        let total = resolution as i64;
+        let (origin_x, origin_z) = (self.long as i64 * total, -(self.lat as i64 + 1) * total);
+        let water_level = self.water_level;
+        let mut sampler = HeightSampler::new(&mut self.tiles, resolution);
+        let mut heights = Vec::with_capacity(n * n);
+        for x in 0..n as i64 {
+            for z in 0..n as i64 {
+                let (sx, sz) = (node.x + x * fine, node.z + z * fine);
+                //like the vertex shader only the 3x3 tiles around the current one are drawn
+                let inside = (-1..=1).contains(&sx.div_euclid(total)) && (-1..=1).contains(&sz.div_euclid(total));
+                let height = if inside { sampler.sample(origin_x + sx, origin_z + sz).unwrap_or(0.0) } else { 0.0 };
+                heights.push(if height < water_level { water_level - 0.01 } else { height });
             }
         }
+        let min_height = heights.iter().copied().fold(f32::INFINITY, f32::min);
+        let max_height = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        if n <= step {
+            return ChunkMeasure { min_height, max_height, error: max_height - min_height };
         }
-    (data, texturedata, vertices_per_row)
-    }
-    pub fn create_collection_of_terrain_data(&mut self, x_chunks:u32, z_chunks:u32, translations:&Vec<[f32;2]>) -> (Vec<Vec<Vertex>>,Vec<Vec<Vertex>>, u32) {
-        let mut data:Vec<Vec<Vertex>> = vec![];
-        let mut texturedata:Vec<Vec<Vertex>> = vec![];
-        let mut vertices_per_row = 0u32;
-        //going through all the chunks and calculating the function to calculate the y values
-        let mut k:u32 = 0;
-        for _i in 0..x_chunks {
-            //self.level_of_detail=5;
-            for _j in 0..z_chunks {
-                self.offsets = translations[k as usize];
-                let dd = self.create_terrain_data();
-                data.push(dd.0);
-                texturedata.push(dd.1);
-                vertices_per_row = dd.2;
-                k += 1;
+        //every fine sample against the coarse cell it falls in
+        let h = |x: usize, z: usize| heights[x * n + z];
+        let last = n - 1;
+        let mut error = 0f32;
+        for x in 0..n {
+            let x0 = (x / step * step).min(last - step);
+            let tx = (x - x0) as f32 / step as f32;
+            for z in 0..n {
+                let z0 = (z / step * step).min(last - step);
+                let tz = (z - z0) as f32 / step as f32;
+                let north = h(x0, z0) + (h(x0 + step, z0) - h(x0, z0)) * tx;
+                let south = h(x0, z0 + step) + (h(x0 + step, z0 + step) - h(x0, z0 + step)) * tx;
+                error = error.max((north + (south - north) * tz - h(x, z)).abs());
             }
         }
-        (data, texturedata, vertices_per_row)
+        ChunkMeasure { min_height, max_height, error }
     }
 
+    pub fn neighbourhood(&mut self) -> Vec<(TileKey, Option<Arc<TileData>>)> {
+        //the 3x3 tiles around the current one in heightmap layer order, None while a tile is loading
+        self.recentre();
+        let resolution = self.resolution();
+        let mut tiles = Vec::with_capacity(heightmap::LAYERS);
+        for dlat in [1, 0, -1] {
+            for dlong in [-1, 0, 1] {
+                let key = TileKey { lat: self.lat + dlat, long: wrap_long(self.long + dlong), resolution };
+                tiles.push((key, self.tiles.try_get(key)));
+            }
+        }
+        tiles
+    }
 
+    pub fn render_moves(&self) -> [f32; 2] {
+        //moves are full resolution samples, a minimised tile is a quarter as wide
+        let scale = self.resolution() as f32 / self.samples_per_degree as f32;
+        [self.moves[0] * scale, self.moves[1] * scale]
+    }
 
-
-
+    pub fn uniform(&self, loaded: u32) -> TerrainUniform {
+        //everything the vertex shader needs to displace the grid, loaded has a bit per heightmap layer that is ready
+        let frame = self.frame();
+        let ramp = &self.ramps[self.ramp];
+        let water = ramp.water().map_or([0.0; 4], |[r, g, b]| [r, g, b, 1.0]);
+        TerrainUniform {
+            tile: self.resolution() as i32,
+            water_level: self.water_level,
+            vertical_scale: frame.vertical_scale,
+            east_west: frame.east_west_scale(),
+            height_max: self.colour_height_max,
+            loaded,
+            show_voids: self.show_voids as u32,
+            stop_count: ramp.stops().len() as u32,
+            normalised: ramp.is_normalised() as u32,
+            stepped: ramp.is_stepped() as u32,
+            _padding: [0; 2],
+            water,
+        }
+    }
 }
@@ -0,0 +1,241 @@
+use std::fmt;
+
+const SAMPLE_SECONDS: f32 = 1.0;//flight time between terrain samples along the projected path
+const HOLD_SECONDS: f32 = 3.0;//an alert stays up this long after its cause goes so it does not flicker
+
+pub trait HeightField {//terrain the alerting looks ahead over, anything from the srtm tiles to a made up slope
+    //metres above sea level at a point east and north of the aircraft in metres, None where it is not known
+    fn height(&mut self, east: f32, north: f32) -> Option<f32>;
+}
+
+impl<F: FnMut(f32, f32) -> Option<f32>> HeightField for F {
+    fn height(&mut self, east: f32, north: f32) -> Option<f32> {
+        self(east, north)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FlightState {//what the alerting needs to know about the aircraft, speeds in metres per second
+    pub altitude: f32,//metres above sea level
+    pub ground_speed: f32,
+    pub vertical_speed: f32,//positive climbing
+    pub heading: f32,//degrees clockwise from north
+    pub climb_gradient: f32,//height gained per metre flown at full power, how steep a pull up can be
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TawsConfig {//seconds of flight time
+    pub look_ahead: f32,//how far along the flight path terrain is checked
+    pub caution: f32,//time to impact that raises CAUTION TERRAIN
+    pub pull_up: f32,//and PULL UP
+    pub clearance: f32,//metres the projected path has to stay above the terrain, at most the floor's minimum clearance
+}
+
+impl Default for TawsConfig {
+    fn default() -> Self {
+        Self { look_ahead: 60.0, caution: 40.0, pull_up: 20.0, clearance: 5.0 }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Alert {//ordered from least to most urgent
+    Clear,
+    Caution,
+    PullUp,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Alert::Clear => "TERRAIN CLEAR",
+            Alert::Caution => "CAUTION TERRAIN",
+            Alert::PullUp => "PULL UP",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Assessment {//the terrain ahead for one position
+    pub time_to_impact: Option<f32>,//seconds until the projected path comes within the clearance of the terrain
+    pub required_gradient: f32,//height per metre flown needed from here to clear everything ahead, 0 or less if none is
+    pub alert: Alert,//what this one assessment calls for, before any hysteresis
+}
+
+pub fn assess(config: &TawsConfig, state: &FlightState, field: &mut impl HeightField) -> Assessment {
+    //fly the current track and vertical speed ahead in steps, comparing the path with the terrain under it
+    //the terrain right underneath is the ground guard's, the path starts one sample ahead
+    let heading = state.heading.to_radians();
+    let (east, north) = (heading.sin(), heading.cos());
+    let mut time_to_impact = None;
+    let mut required_gradient = f32::NEG_INFINITY;
+    let steps = (config.look_ahead / SAMPLE_SECONDS).ceil() as u32;
+    for step in 1..=steps {
+        let t = (step as f32 * SAMPLE_SECONDS).min(config.look_ahead);
+        let distance = state.ground_speed * t;
+        let Some(ground) = field.height(east * distance, north * distance) else { continue };//unknown terrain raises nothing
+        let above = ground + config.clearance;
+        if time_to_impact.is_none() && state.altitude + state.vertical_speed * t < above {
+            time_to_impact = Some(t);
+        }
+        if distance >= 1.0 {//hovering over it no gradient clears it, that is the time to impact's job
+            required_gradient = required_gradient.max((above - state.altitude) / distance);
+        }
+    }
+    let required_gradient = required_gradient.max(0.0);
+    let alert = match time_to_impact {
+        Some(t) if t <= config.pull_up || required_gradient > state.climb_gradient => Alert::PullUp,
+        Some(t) if t <= config.caution => Alert::Caution,
+        _ => Alert::Clear,
+    };
+    Assessment { time_to_impact, required_gradient, alert }
+}
+
+#[derive(Clone, Debug)]
+pub struct Taws {//alert state from one assessment to the next
+    pub config: TawsConfig,
+    alert: Alert,
+    calm: f32,//seconds the assessments have called for less than the alert being shown
+}
+
+impl Taws {
+    pub fn new(config: TawsConfig) -> Self {
+        Self { config, alert: Alert::Clear, calm: 0.0 }
+    }
+
+    pub fn alert(&self) -> Alert {
+        self.alert
+    }
+
+    pub fn update(&mut self, state: &FlightState, field: &mut impl HeightField, seconds: f32) -> Option<Alert> {
+        //assess the terrain ahead, the alert goes up straight away but only comes down once it has been calm a while
+        //returns the new alert when it changes
+        let wanted = assess(&self.config, state, field).alert;
+        let previous = self.alert;
+        if wanted >= self.alert {
+            self.alert = wanted;
+            self.calm = 0.0;
+        } else {
+            self.calm += seconds;
+            if self.calm >= HOLD_SECONDS {
+                self.alert = wanted;
+                self.calm = 0.0;
+            }
+        }
+        (self.alert != previous).then_some(self.alert)
+    }
+
+    pub fn reset(&mut self) {
+        self.alert = Alert::Clear;
+        self.calm = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flying(altitude: f32, heading: f32, climb_gradient: f32) -> FlightState {
+        //level at 50 m/s, samples fall every 50 m along the track
+        FlightState { altitude, ground_speed: 50.0, vertical_speed: 0.0, heading, climb_gradient }
+    }
+
+    fn flat(_east: f32, _north: f32) -> Option<f32> {
+        Some(100.0)
+    }
+
+    fn ridge_north(_east: f32, north: f32) -> Option<f32> {
+        //600 m high from 1500 to 1600 m north, 30 to 32 seconds ahead
+        Some(if (1475.0..1625.0).contains(&north) { 600.0 } else { 0.0 })
+    }
+
+    fn slope_north(_east: f32, north: f32) -> Option<f32> {
+        //rises a metre every 4 flown
+        Some(north.max(0.0) / 4.0)
+    }
+
+    #[test]
+    fn flat_ground_is_clear() {
+        let assessment = assess(&TawsConfig::default(), &flying(500.0, 0.0, 0.1), &mut flat);
+        assert_eq!(assessment, Assessment { time_to_impact: None, required_gradient: 0.0, alert: Alert::Clear });
+    }
+
+    #[test]
+    fn skimming_at_the_floor_is_clear() {
+        //the minimum clearance is the ground guard's, only the path ahead is checked
+        let config = TawsConfig::default();
+        let assessment = assess(&config, &flying(110.0, 0.0, 0.1), &mut flat);
+        assert_eq!(assessment.alert, Alert::Clear);
+        let mut cliff_behind = |_east: f32, north: f32| Some(if north < 25.0 { 1000.0 } else { 0.0 });
+        assert_eq!(assess(&config, &flying(500.0, 0.0, 0.1), &mut cliff_behind).alert, Alert::Clear);
+    }
+
+    #[test]
+    fn unknown_terrain_raises_nothing() {
+        let mut unknown = |_east: f32, _north: f32| None;
+        assert_eq!(assess(&TawsConfig::default(), &flying(0.0, 0.0, 0.0), &mut unknown).alert, Alert::Clear);
+    }
+
+    #[test]
+    fn ridge_ahead_is_a_caution() {
+        let assessment = assess(&TawsConfig::default(), &flying(500.0, 0.0, 0.1), &mut ridge_north);
+        assert_eq!(assessment.time_to_impact, Some(30.0));
+        assert!((assessment.required_gradient - 105.0 / 1500.0).abs() < 1e-6);
+        assert_eq!(assessment.alert, Alert::Caution);
+    }
+
+    #[test]
+    fn close_ridge_is_a_pull_up() {
+        //the same ridge 500 m east, 10 seconds away
+        let mut ridge_east = |east: f32, north: f32| ridge_north(north, east + 1000.0);
+        let assessment = assess(&TawsConfig::default(), &flying(500.0, 90.0, 0.1), &mut ridge_east);
+        assert_eq!(assessment.time_to_impact, Some(10.0));
+        assert!((assessment.required_gradient - 105.0 / 500.0).abs() < 1e-6);
+        assert_eq!(assessment.alert, Alert::PullUp);
+    }
+
+    #[test]
+    fn ridge_beyond_the_look_ahead_is_clear() {
+        let config = TawsConfig { look_ahead: 25.0, caution: 25.0, pull_up: 20.0, ..TawsConfig::default() };
+        let assessment = assess(&config, &flying(500.0, 0.0, 0.1), &mut ridge_north);
+        assert_eq!(assessment.time_to_impact, None);
+        assert_eq!(assessment.alert, Alert::Clear);
+    }
+
+    #[test]
+    fn slope_steeper_than_the_climb_is_a_pull_up() {
+        //impact at 1250 m is 25 seconds out, past the pull up time, but the slope outclimbs the aircraft
+        let assessment = assess(&TawsConfig::default(), &flying(305.0, 0.0, 0.1), &mut slope_north);
+        assert_eq!(assessment.time_to_impact, Some(25.0));
+        assert!((assessment.required_gradient - 0.15).abs() < 1e-6);
+        assert_eq!(assessment.alert, Alert::PullUp);
+        //an aircraft that can outclimb it only gets the caution
+        let assessment = assess(&TawsConfig::default(), &flying(305.0, 0.0, 0.2), &mut slope_north);
+        assert_eq!(assessment.alert, Alert::Caution);
+    }
+
+    #[test]
+    fn climbing_clears_the_ridge() {
+        //5 m/s up puts the path 150 m higher by the ridge, above its clearance
+        let state = FlightState { vertical_speed: 5.0, ..flying(500.0, 0.0, 0.1) };
+        assert_eq!(assess(&TawsConfig::default(), &state, &mut ridge_north).time_to_impact, None);
+    }
+
+    #[test]
+    fn alerts_rise_at_once_and_fall_after_the_hold() {
+        let mut taws = Taws::new(TawsConfig::default());
+        let state = flying(500.0, 0.0, 0.1);
+        let mut ridge_close = |east: f32, north: f32| ridge_north(east, north + 1000.0);
+        assert_eq!(taws.update(&state, &mut ridge_close, 1.0), Some(Alert::PullUp));
+        assert_eq!(taws.update(&state, &mut ridge_close, 1.0), None);
+        //calm for less than the hold keeps the alert up, and trouble again restarts the hold
+        assert_eq!(taws.update(&state, &mut flat, HOLD_SECONDS - 1.0), None);
+        assert_eq!(taws.update(&state, &mut ridge_close, 1.0), None);
+        assert_eq!(taws.update(&state, &mut flat, HOLD_SECONDS - 1.0), None);
+        assert_eq!(taws.alert(), Alert::PullUp);
+        assert_eq!(taws.update(&state, &mut flat, 1.0), Some(Alert::Clear));
+        //a caution raised straight after still goes up at once
+        assert_eq!(taws.update(&state, &mut ridge_north, 0.1), Some(Alert::Caution));
+        taws.reset();
+        assert_eq!(taws.alert(), Alert::Clear);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use super::elevation::ElevationSource;
+use super::void_fill::{fill_voids, VoidFill};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TileKey {//identifies a loaded tile, resolution is the samples per degree it is loaded at whatever the file has
+    pub lat: i32,
+    pub long: i32,
+    pub resolution: u32,
+}
+
+pub struct TileData {//heights in metres of one tile stored row by row from north to south, including the edge shared with the east and south tiles
+    pub size: u32,
+    heights: Vec<f32>,
+    voids: Vec<bool>,//true where the file had no data and the height was filled in
+}
+impl TileData {
+    pub fn get(&self, x: u32, z: u32) -> f32 {
+        self.heights[(z * self.size + x) as usize]
+    }
+
+    pub fn heights(&self) -> &[f32] {
+        &self.heights
+    }
+
+    pub fn voids(&self) -> &[bool] {
+        &self.voids
+    }
+}
+
+enum Slot {//a tile is either still being loaded by a worker or ready to be sampled
+    Pending,
+    Ready(Arc<TileData>),
+}
+
+pub struct TileCache {//tiles loaded in the background by a fixed pool of workers and evicted least recently used first
+    capacity: usize,
+    slots: HashMap<TileKey, Slot>,
+    lru: VecDeque<TileKey>,//front is the least recently used tile
+    jobs: Option<mpsc::Sender<TileKey>>,
+    results: mpsc::Receiver<(TileKey, TileData)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl TileCache {
+    pub fn new(source: Arc<dyn ElevationSource>, void_fill: VoidFill, worker_count: usize, capacity: usize) -> Self {
+        let (job_tx, job_rx) = mpsc::channel::<TileKey>();
+        let (result_tx, result_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));//workers take turns pulling jobs from the same queue
+        let mut workers = vec![];
+        for _ in 0..worker_count.max(1) {
+            let job_rx = Arc::clone(&job_rx);
+            let result_tx = result_tx.clone();
+            let source = Arc::clone(&source);
+            workers.push(thread::spawn(move || loop {
+                let job = job_rx.lock().unwrap().recv();
+                match job {
+                    Ok(key) => {
+                        if result_tx.send((key, load_tile(source.as_ref(), void_fill, key))).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,//the cache was dropped
+                }
+            }));
+        }
+        Self {
+            capacity: capacity.max(1),
+            slots: HashMap::new(),
+            lru: VecDeque::new(),
+            jobs: Some(job_tx),
+            results: result_rx,
+            workers,
+        }
+    }
+
+    pub fn request(&mut self, key: TileKey) {
+        //queue a tile for loading unless it is already loaded or on its way
+        if self.slots.contains_key(&key) {
+            return;
+        }
+        if let Some(jobs) = &self.jobs {
+            if jobs.send(key).is_ok() {
+                self.slots.insert(key, Slot::Pending);
+            }
+        }
+    }
+
+    pub fn try_get(&mut self, key: TileKey) -> Option<Arc<TileData>> {
+        //never blocks, tiles that are not loaded yet are requested and None is returned
+        match self.slots.get(&key) {
+            Some(Slot::Ready(data)) => {
+                let data = Arc::clone(data);
+                self.touch(key);
+                Some(data)
+            }
+            Some(Slot::Pending) => None,
+            None => {
+                self.request(key);
+                None
+            }
+        }
+    }
+
+    pub fn poll(&mut self) -> Vec<TileKey> {
+        //move finished tiles from the workers into the cache, returns the ones that arrived
+        let mut arrived = vec![];
+        while let Ok((key, data)) = self.results.try_recv() {
+            self.slots.insert(key, Slot::Ready(Arc::new(data)));
+            self.touch(key);
+            arrived.push(key);
+        }
+        self.evict();
+        arrived
+    }
+
+    pub fn pending(&self) -> usize {
+        //tiles requested that the workers have not finished yet
+        self.slots.values().filter(|slot| matches!(slot, Slot::Pending)).count()
+    }
+
+    fn touch(&mut self, key: TileKey) {
+        if let Some(i) = self.lru.iter().position(|k| *k == key) {
+            self.lru.remove(i);
+        }
+        self.lru.push_back(key);
+    }
+
+    fn evict(&mut self) {
+        while self.lru.len() > self.capacity {
+            if let Some(key) = self.lru.pop_front() {
+                self.slots.remove(&key);
+            }
+        }
+    }
+}
+
+impl Drop for TileCache {
+    fn drop(&mut self) {
+        //closing the job queue lets every worker finish its current tile and exit
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn load_tile(source: &dyn ElevationSource, void_fill: VoidFill, key: TileKey) -> TileData {
+    //read one tile in metres, fill any voids and resample it to the resolution asked for
+    let size = key.resolution + 1;//tiles repeat the neighbouring tiles first row and column
+    let raw = match source.load(key.lat, key.long) {
+        Ok(Some(raw)) => raw,
+        Ok(None) => {//nothing in the data directory here, usually sea
+            return TileData { size, heights: vec![0.0; (size * size) as usize], voids: vec![false; (size * size) as usize] };
+        }
+        Err(err) => {
+            eprintln!("tile {} {} could not be loaded ({}), using sea level", key.lat, key.long, err);
+            return TileData { size, heights: vec![0.0; (size * size) as usize], voids: vec![false; (size * size) as usize] };
+        }
+    };
+    let extent = raw.extent as usize;
+    let voids = raw.voids;
+    let mut heights = raw.heights;
+    fill_voids(&mut heights, &voids, extent, void_fill);
+    if raw.extent == size {
+        return TileData { size, heights, voids };
+    }
+    //bilinear resampling covers both skipping samples for minimised tiles and srtm3 tiles next to srtm1 ones
+    let step = (raw.extent - 1) as f32 / key.resolution as f32;
+    let mut resampled = Vec::with_capacity((size * size) as usize);
+    let mut resampled_voids = Vec::with_capacity((size * size) as usize);
+    for z in 0..size {
+        let fz = z as f32 * step;
+        let z0 = (fz.floor() as usize).min(extent - 2);
+        let tz = fz - z0 as f32;
+        for x in 0..size {
+            let fx = x as f32 * step;
+            let x0 = (fx.floor() as usize).min(extent - 2);
+            let tx = fx - x0 as f32;
+            let i = z0 * extent + x0;
+            let top = heights[i] + (heights[i + 1] - heights[i]) * tx;
+            let bottom = heights[i + extent] + (heights[i + extent + 1] - heights[i + extent]) * tx;
+            resampled.push(top + (bottom - top) * tz);
+            let nearest = (z0 + tz.round() as usize) * extent + x0 + tx.round() as usize;
+            resampled_voids.push(voids[nearest]);
+        }
+    }
+    TileData { size, heights: resampled, voids: resampled_voids }
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+pub const DEFAULT_DATA_DIR: &str = "src/Scotlandhgt";//directory searched when nothing else is configured
+pub const DEFAULT_SAMPLES_PER_DEGREE: u32 = 3600;//srtm1, used when the starting tile is missing
+pub const DATA_DIR_ENV: &str = "SRTM_DATA_DIR";//environment variable overriding the data directory
+
+#[derive(Clone, Debug)]
+pub struct TileResolver {//Resolves srtm tile files from a root directory
+    pub root: PathBuf,
+}
+
+impl TileResolver {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn tile_name(lat: i32, long: i32) -> String {
+        //srtm names use the south west corner of the tile e.g. N55W005 or S34E151
+        let ns = if lat >= 0 { 'N' } else { 'S' };
+        let ew = if long >= 0 { 'E' } else { 'W' };
+        format!("{}{:02}{}{:03}.hgt", ns, lat.abs(), ew, long.abs())
+    }
+
+    pub fn path(&self, lat: i32, long: i32) -> PathBuf {
+        self.root.join(Self::tile_name(lat, wrap_long(long)))
+    }
+}
+
+pub fn wrap_long(long: i32) -> i32 {
+    //keeps longitudes in -180..=179 so moving across the antimeridian finds the right tile
+    (long + 180).rem_euclid(360) - 180
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_names_carry_the_hemisphere_and_padding() {
+        assert_eq!(TileResolver::tile_name(55, -5), "N55W005.hgt");
+        assert_eq!(TileResolver::tile_name(-34, 151), "S34E151.hgt");
+        assert_eq!(TileResolver::tile_name(0, 0), "N00E000.hgt");
+        assert_eq!(TileResolver::tile_name(-1, -1), "S01W001.hgt");
+        assert_eq!(TileResolver::tile_name(7, 9), "N07E009.hgt");
+        assert_eq!(TileResolver::tile_name(-90, -180), "S90W180.hgt");
+    }
+
+    #[test]
+    fn longitudes_wrap_across_the_antimeridian() {
+        assert_eq!(wrap_long(179), 179);
+        assert_eq!(wrap_long(180), -180);
+        assert_eq!(wrap_long(181), -179);
+        assert_eq!(wrap_long(-180), -180);
+        assert_eq!(wrap_long(-181), 179);
+        assert_eq!(wrap_long(540), -180);
+        assert_eq!(wrap_long(-5), -5);
+    }
+
+    #[test]
+    fn paths_use_the_wrapped_tile() {
+        let resolver = TileResolver::new("/data");
+        assert_eq!(resolver.path(10, 180), PathBuf::from("/data/N10W180.hgt"));
+        assert_eq!(resolver.path(-10, -181), PathBuf::from("/data/S10E179.hgt"));
+    }
+}
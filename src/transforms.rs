@@ -1,4 +1,3 @@
-use std::f32::consts::PI;
 use cgmath::*;
 
 pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
@@ -11,25 +10,51 @@ pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
-pub fn create_projection(aspect:f32, is_perspective:bool) -> Matrix4<f32> {
+pub struct Lens {//perspective settings, fov is the vertical field of view in radians
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+pub struct Frustum {//left, right, bottom, top, near and far planes with their normals pointing inside
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    pub fn from_view_projection(vp: &Matrix4<f32>) -> Self {
+        //planes straight from the rows of the view projection matrix, wgpu clip space has z from 0 to w
+        let (r0, r1, r2, r3) = (vp.row(0), vp.row(1), vp.row(2), vp.row(3));
+        Self { planes: [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2] }
+    }
+
+    pub fn intersects_box(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        //a box is outside once its corner furthest along some plane's normal is still behind that plane
+        self.planes.iter().all(|p| {
+            let x = if p.x >= 0.0 { max[0] } else { min[0] };
+            let y = if p.y >= 0.0 { max[1] } else { min[1] };
+            let z = if p.z >= 0.0 { max[2] } else { min[2] };
+            p.x * x + p.y * y + p.z * z + p.w >= 0.0
+        })
+    }
+}
+
+pub fn create_projection(aspect:f32, is_perspective:bool, lens:&Lens) -> Matrix4<f32> {
     //creation of projection matrix
-    let project_mat:Matrix4<f32>;
     if is_perspective {
-        project_mat = OPENGL_TO_WGPU_MATRIX * perspective(Rad(2.0*PI/5.0), aspect, 0.1, 1000.0);
+        OPENGL_TO_WGPU_MATRIX * perspective(Rad(lens.fov), aspect, lens.near, lens.far)
     } else {
-        project_mat = OPENGL_TO_WGPU_MATRIX * ortho(-4.0, 4.0, -3.0, 3.0, -1.0, 6.0);
+        OPENGL_TO_WGPU_MATRIX * ortho(-4.0, 4.0, -3.0, 3.0, -1.0, 6.0)
     }
-    project_mat
 }
 
 
-pub fn create_view_projection(camera_position: Point3<f32>, look_direction: Point3<f32>, up_direction: Vector3<f32>, aspect:f32) -> (Matrix4<f32>, Matrix4<f32>, Matrix4<f32>) {
+pub fn create_view_projection(camera_position: Point3<f32>, look_direction: Point3<f32>, up_direction: Vector3<f32>, aspect:f32, lens:&Lens) -> (Matrix4<f32>, Matrix4<f32>, Matrix4<f32>) {
 
     //construct view matrix
     let view_mat = Matrix4::look_at_rh(camera_position, look_direction, up_direction);
 
     //construct projection matrix
-    let project_mat = OPENGL_TO_WGPU_MATRIX * perspective(Rad(2.0*PI/5.0), aspect, 0.1, 1000.0);
+    let project_mat = create_projection(aspect, true, lens);
 
     //contruct view-projection matrix
     let view_project_mat = project_mat * view_mat;
@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+pub const SRTM_VOID: i16 = -32768;//srtm no data value
+pub const VOID_FILL_ENV: &str = "SRTM_VOID_FILL";//environment variable choosing the fill strategy
+
+const SEARCH_RADIUS: usize = 64;//samples searched in each direction by inverse distance weighting
+const LAPLACIAN_ITERATIONS: usize = 500;
+const LAPLACIAN_TOLERANCE: f32 = 0.01;//metres, relaxation stops once no height changes more than this
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum VoidFill {//how holes in a tile are filled in
+    Nearest,//height of the closest valid sample
+    #[default]
+    InverseDistance,//weighted by distance to the first valid sample in eight directions
+    Laplacian,//smooth membrane over the hole with the valid samples around it fixed
+}
+
+impl FromStr for VoidFill {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(VoidFill::Nearest),
+            "idw" | "inverse-distance" => Ok(VoidFill::InverseDistance),
+            "laplacian" => Ok(VoidFill::Laplacian),
+            _ => Err(format!("unknown void fill '{}', expected nearest, idw or laplacian", s)),
+        }
+    }
+}
+
+pub fn fill_voids(heights: &mut [f32], mask: &[bool], size: usize, strategy: VoidFill) {
+    //heights and mask are size x size row by row, mask is true where the sample was void
+    let voids: Vec<usize> = (0..heights.len()).filter(|&i| mask[i]).collect();
+    if voids.is_empty() {
+        return;
+    }
+    if voids.len() == heights.len() {//nothing to fill from so the tile is left at sea level
+        heights.iter_mut().for_each(|h| *h = 0.0);
+        return;
+    }
+    //every strategy starts from the nearest valid height so far away samples always get a value
+    fill_nearest(heights, mask, size);
+    match strategy {
+        VoidFill::Nearest => {}
+        VoidFill::InverseDistance => fill_inverse_distance(heights, mask, size, &voids),
+        VoidFill::Laplacian => fill_laplacian(heights, size, &voids),
+    }
+}
+
+fn fill_nearest(heights: &mut [f32], mask: &[bool], size: usize) {
+    //breadth first search outwards from the valid samples around each hole
+    let mut filled: Vec<bool> = mask.iter().map(|void| !void).collect();
+    let mut queue: VecDeque<usize> = (0..heights.len())
+        .filter(|&i| filled[i] && neighbours(i, size).any(|n| mask[n]))
+        .collect();
+    while let Some(i) = queue.pop_front() {
+        for n in neighbours(i, size) {
+            if !filled[n] {
+                heights[n] = heights[i];
+                filled[n] = true;
+                queue.push_back(n);
+            }
+        }
+    }
+}
+
+fn fill_inverse_distance(heights: &mut [f32], mask: &[bool], size: usize, voids: &[usize]) {
+    const DIRECTIONS: [(i64, i64); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+    let nearest = heights.to_vec();
+    for &i in voids {
+        let (x, z) = ((i % size) as i64, (i / size) as i64);
+        let mut weights = 0.0f32;
+        let mut total = 0.0f32;
+        for (dx, dz) in DIRECTIONS {
+            for step in 1..=SEARCH_RADIUS as i64 {
+                let (sx, sz) = (x + dx * step, z + dz * step);
+                if sx < 0 || sz < 0 || sx >= size as i64 || sz >= size as i64 {
+                    break;
+                }
+                let j = sz as usize * size + sx as usize;
+                if !mask[j] {
+                    let distance2 = ((dx * step).pow(2) + (dz * step).pow(2)) as f32;
+                    weights += 1.0 / distance2;
+                    total += nearest[j] / distance2;
+                    break;
+                }
+            }
+        }
+        if weights > 0.0 {//holes wider than the search radius keep the nearest height
+            heights[i] = total / weights;
+        }
+    }
+}
+
+fn fill_laplacian(heights: &mut [f32], size: usize, voids: &[usize]) {
+    //gauss seidel relaxation, each void becomes the average of its neighbours
+    for _ in 0..LAPLACIAN_ITERATIONS {
+        let mut change = 0.0f32;
+        for &i in voids {
+            let mut sum = 0.0;
+            let mut count = 0.0;
+            for n in neighbours(i, size) {
+                sum += heights[n];
+                count += 1.0;
+            }
+            let value = sum / count;
+            change = change.max((value - heights[i]).abs());
+            heights[i] = value;
+        }
+        if change < LAPLACIAN_TOLERANCE {
+            break;
+        }
+    }
+}
+
+fn neighbours(i: usize, size: usize) -> impl Iterator<Item = usize> {
+    //indices of the four samples next to i that are inside the tile
+    let (x, z) = (i % size, i / size);
+    [
+        (x > 0).then(|| i - 1),
+        (x + 1 < size).then(|| i + 1),
+        (z > 0).then(|| i - size),
+        (z + 1 < size).then(|| i + size),
+    ].into_iter().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRATEGIES: [VoidFill; 3] = [VoidFill::Nearest, VoidFill::InverseDistance, VoidFill::Laplacian];
+
+    fn grid(size: usize, height: f32, voids: &[usize]) -> (Vec<f32>, Vec<bool>) {
+        //a flat tile with the given samples void
+        let mut heights = vec![height; size * size];
+        let mut mask = vec![false; size * size];
+        for &i in voids {
+            heights[i] = SRTM_VOID as f32;
+            mask[i] = true;
+        }
+        (heights, mask)
+    }
+
+    #[test]
+    fn single_void_takes_the_height_around_it() {
+        for strategy in STRATEGIES {
+            let (mut heights, mask) = grid(5, 120.0, &[12]);
+            fill_voids(&mut heights, &mask, 5, strategy);
+            assert!((heights[12] - 120.0).abs() < LAPLACIAN_TOLERANCE, "{:?} filled {}", strategy, heights[12]);
+            assert!(heights.iter().all(|h| (*h - 120.0).abs() < LAPLACIAN_TOLERANCE));
+        }
+    }
+
+    #[test]
+    fn all_void_tile_is_left_at_sea_level() {
+        for strategy in STRATEGIES {
+            let (mut heights, mask) = grid(4, 50.0, &(0..16).collect::<Vec<_>>());
+            fill_voids(&mut heights, &mask, 4, strategy);
+            assert_eq!(heights, vec![0.0; 16], "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn voids_on_the_edge_and_corners_are_filled() {
+        //a corner, an edge sample and a whole row along the south edge
+        let voids = [0, 2, 20, 21, 22, 23, 24];
+        for strategy in STRATEGIES {
+            let (mut heights, mask) = grid(5, 75.0, &voids);
+            fill_voids(&mut heights, &mask, 5, strategy);
+            for i in voids {
+                assert!((heights[i] - 75.0).abs() < LAPLACIAN_TOLERANCE, "{:?} filled {} with {}", strategy, i, heights[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn fills_lie_between_the_heights_around_them() {
+        //a void between a 0 m west half and a 100 m east half
+        for strategy in STRATEGIES {
+            let mut heights: Vec<f32> = (0..25).map(|i| if i % 5 < 2 { 0.0 } else { 100.0 }).collect();
+            let mut mask = vec![false; 25];
+            heights[12] = SRTM_VOID as f32;
+            mask[12] = true;
+            fill_voids(&mut heights, &mask, 5, strategy);
+            assert!((0.0..=100.0).contains(&heights[12]), "{:?} filled {}", strategy, heights[12]);
+        }
+    }
+
+    #[test]
+    fn tiles_without_voids_are_untouched() {
+        let mut heights: Vec<f32> = (0..9).map(|i| i as f32).collect();
+        fill_voids(&mut heights, &[false; 9], 3, VoidFill::Laplacian);
+        assert_eq!(heights, (0..9).map(|i| i as f32).collect::<Vec<_>>());
+    }
+}